@@ -0,0 +1,335 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Mutex;
+
+use crate::classify::classify;
+use crate::config::BackupConfig;
+use crate::database::{Database, EntryRecord, Snippet};
+
+const FORMAT: &str = "clippoo-backup";
+const VERSION: u32 = 1;
+/// Name of the JSON Lines file inside an archive
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// One line of a backup
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Record {
+    Header { format: String, version: u32, exported_at: DateTime<Utc> },
+    Entry(EntryRecord),
+    Snippet(SnippetRecord),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnippetRecord {
+    name: String,
+    #[serde(default)]
+    folder: String,
+    #[serde(default)]
+    keyword: Option<String>,
+    content: String,
+}
+
+/// Which history entries to export; every entry when nothing is set
+#[derive(Debug, Default)]
+pub struct ExportFilter {
+    pub tag: Option<String>,
+    pub content_type: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub snippets: bool,
+}
+
+impl ExportFilter {
+    /// Export everything, snippets included
+    pub fn all() -> Self {
+        Self { snippets: true, ..Self::default() }
+    }
+
+    fn matches(&self, record: &EntryRecord) -> bool {
+        let tag = self.tag.as_deref().map(|tag| tag.trim_start_matches('#'));
+        tag.is_none_or(|tag| record.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            && self.content_type.as_deref().is_none_or(|name| {
+                let stored = record.content_type.as_deref();
+                stored.unwrap_or_else(|| classify(&record.content).content_type.name()) == name
+            })
+            && self.since.is_none_or(|since| record.timestamp >= since)
+    }
+}
+
+/// What an export or restore covered
+pub struct Summary {
+    pub entries: usize,
+    pub snippets: usize,
+}
+
+/// Write history entries and snippets as JSON Lines, or with `archive` as a
+/// gzipped tar holding the lines and the blob files of large entries
+pub async fn export(db: &Database, path: &Path, filter: &ExportFilter, archive: bool) -> Result<Summary> {
+    let mut lines = vec![Record::Header { format: FORMAT.into(), version: VERSION, exported_at: Utc::now() }];
+    let mut blobs = Vec::new();
+
+    for mut record in db.export_entries().await? {
+        if !filter.matches(&record) {
+            continue;
+        }
+        match record.blob.take() {
+            Some(blob) if archive => {
+                blobs.push((format!("blobs/{}", blob), std::mem::take(&mut record.content).into_bytes()));
+                record.blob = Some(blob);
+            }
+            _ => {}
+        }
+        lines.push(Record::Entry(record));
+    }
+    let entries = lines.len() - 1;
+
+    if filter.snippets {
+        for snippet in db.get_snippets().await? {
+            let Snippet { name, folder, keyword, content, .. } = snippet;
+            lines.push(Record::Snippet(SnippetRecord { name, folder, keyword, content }));
+        }
+    }
+    let snippets = lines.len() - 1 - entries;
+
+    let mut jsonl = String::new();
+    for line in &lines {
+        jsonl.push_str(&serde_json::to_string(line)?);
+        jsonl.push('\n');
+    }
+
+    let data = if archive {
+        let mut files = vec![(HISTORY_FILE.to_string(), jsonl.into_bytes())];
+        files.extend(blobs);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&tar::write(&files)?)?;
+        encoder.finish()?
+    } else {
+        jsonl.into_bytes()
+    };
+
+    // Written in full before replacing an older file of the same name
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, data)?;
+    std::fs::rename(&partial, path)?;
+
+    Ok(Summary { entries, snippets })
+}
+
+/// Add the entries and snippets of a backup written by `export`, merging
+/// entries by content like new copies are
+pub async fn restore(db: &Database, path: &Path) -> Result<Summary> {
+    let data = std::fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+
+    let (jsonl, mut blobs) = if data.starts_with(&[0x1f, 0x8b]) {
+        let mut tar = Vec::new();
+        GzDecoder::new(data.as_slice()).read_to_end(&mut tar)?;
+        let mut files = tar::read(&tar)?;
+        let jsonl = files.remove(HISTORY_FILE).context("The archive has no history.jsonl")?;
+        (jsonl, files)
+    } else {
+        (data, HashMap::new())
+    };
+
+    let mut entries = Vec::new();
+    let mut snippets = Vec::new();
+    for (number, line) in std::str::from_utf8(&jsonl)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(line).with_context(|| format!("Invalid line {}", number + 1))?;
+        match record {
+            Record::Header { format, version, .. } => {
+                if format != FORMAT || version > VERSION {
+                    anyhow::bail!("Unsupported backup format {} version {}", format, version);
+                }
+            }
+            Record::Entry(mut entry) => {
+                if let Some(blob) = entry.blob.take() {
+                    let content = blobs.remove(&format!("blobs/{}", blob)).context("A blob file is missing")?;
+                    entry.content = String::from_utf8(content)?;
+                }
+                entries.push(entry);
+            }
+            Record::Snippet(snippet) => snippets.push(snippet),
+        }
+    }
+
+    let summary = Summary { entries: entries.len(), snippets: snippets.len() };
+    let added = db.restore_entries(entries).await?;
+    info!("Restored {} entries, {} of them new", summary.entries, added);
+    db.save_snippets(snippets.iter().map(|s| (s.folder.as_str(), s.name.as_str(), s.keyword.as_deref(), s.content.as_str())))
+        .await?;
+    Ok(summary)
+}
+
+/// Keep rolling backups in a `backups` directory next to the database,
+/// writing one whenever the newest is older than the interval
+pub async fn run_scheduled(db: Arc<Mutex<Database>>, config: BackupConfig) {
+    let interval = Duration::from_secs(config.interval_hours.max(1) * 3600);
+    let dir = db.lock().await.path().with_file_name("backups");
+
+    loop {
+        let due = newest_backup(&dir)
+            .and_then(|(_, written)| (written + interval).duration_since(SystemTime::now()).ok())
+            .unwrap_or_default();
+        tokio::time::sleep(due).await;
+
+        match backup_now(&db, &dir, config.keep).await {
+            Ok(path) => info!("Wrote backup {}", path.display()),
+            Err(e) => {
+                warn!("Failed to write a backup: {:#}", e);
+                tokio::time::sleep(interval).await;
+            }
+        }
+    }
+}
+
+async fn backup_now(db: &Mutex<Database>, dir: &Path, keep: usize) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("history-{}.tar.gz", Utc::now().format("%Y%m%d-%H%M%S")));
+    export(&*db.lock().await, &path, &ExportFilter::all(), true).await?;
+
+    // Names sort by date, oldest first
+    let mut backups = backups(dir)?;
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep.max(1));
+    for old in &backups[..excess] {
+        std::fs::remove_file(old)?;
+    }
+    Ok(path)
+}
+
+fn backups(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut backups = Vec::new();
+    for file in std::fs::read_dir(dir)? {
+        let path = file?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with("history-") && name.ends_with(".tar.gz") {
+            backups.push(path);
+        }
+    }
+    Ok(backups)
+}
+
+fn newest_backup(dir: &Path) -> Option<(PathBuf, SystemTime)> {
+    backups(dir)
+        .ok()?
+        .into_iter()
+        .filter_map(|path| Some((path.clone(), std::fs::metadata(&path).ok()?.modified().ok()?)))
+        .max_by_key(|(_, written)| *written)
+}
+
+/// Just enough of the ustar format to bundle a backup with its blob files
+mod tar {
+    use anyhow::{Context, Result};
+    use std::collections::HashMap;
+
+    const BLOCK: usize = 512;
+
+    pub fn write(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+        let mut tar = Vec::new();
+        for (name, data) in files {
+            if name.len() >= 100 {
+                anyhow::bail!("File name too long for the archive: {}", name);
+            }
+
+            let mut header = [0u8; BLOCK];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..108].copy_from_slice(b"0000644\0");
+            header[108..116].copy_from_slice(b"0000000\0");
+            header[116..124].copy_from_slice(b"0000000\0");
+            header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+            header[136..148].copy_from_slice(format!("{:011o}\0", chrono::Utc::now().timestamp()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[263..265].copy_from_slice(b"00");
+            // The checksum is computed with its own field set to spaces
+            header[148..156].copy_from_slice(b"        ");
+            let checksum: u32 = header.iter().map(|byte| *byte as u32).sum();
+            header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+            tar.extend_from_slice(&header);
+            tar.extend_from_slice(data);
+            tar.resize(tar.len().next_multiple_of(BLOCK), 0);
+        }
+        // The archive ends with two empty blocks
+        tar.resize(tar.len() + 2 * BLOCK, 0);
+        Ok(tar)
+    }
+
+    /// The regular files of an archive, by name
+    pub fn read(tar: &[u8]) -> Result<HashMap<String, Vec<u8>>> {
+        let mut files = HashMap::new();
+        let mut offset = 0;
+        while let Some(header) = tar.get(offset..offset + BLOCK) {
+            if header.iter().all(|byte| *byte == 0) {
+                break;
+            }
+
+            let field = |range: std::ops::Range<usize>| {
+                let bytes = &header[range];
+                String::from_utf8_lossy(&bytes[..bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len())]).into_owned()
+            };
+            let name = field(0..100);
+            let size = usize::from_str_radix(field(124..136).trim(), 8).context("Invalid archive entry size")?;
+            let data = tar.get(offset + BLOCK..offset + BLOCK + size).context("Truncated archive")?;
+            if matches!(header[156], b'0' | 0) {
+                files.insert(name, data.to_vec());
+            }
+            offset += BLOCK + size.next_multiple_of(BLOCK);
+        }
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DedupPolicy;
+
+    #[tokio::test]
+    async fn test_archive_round_trip() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let source = Database::open(&temp_dir.path().join("source/clipboard.db")).await?.with_blob_threshold(64);
+        let large = "large entry\n".repeat(100);
+        let tagged = source.add_entry("tagged".to_string()).await?;
+        source.tag_entry(tagged, "work").await?;
+        source.add_entry(large.clone()).await?;
+        source.save_snippet("mail", "Signature", Some("sig"), "Regards").await?;
+
+        let archive = temp_dir.path().join("backup.tar.gz");
+        let exported = export(&source, &archive, &ExportFilter::all(), true).await?;
+        assert_eq!((exported.entries, exported.snippets), (2, 1));
+
+        // Restoring merges by content: "tagged " is a near-duplicate here
+        let mut target = Database::open(&temp_dir.path().join("target/clipboard.db")).await?;
+        target.set_dedup_policy(DedupPolicy::default()).await?;
+        let existing = target.add_entry("tagged ".to_string()).await?;
+        restore(&target, &archive).await?;
+        restore(&target, &archive).await?;
+
+        let entries = target.get_recent_entries(10).await?;
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().any(|entry| entry.content == large));
+        assert_eq!(target.entries_with_tag("work").await?[0].id, existing);
+        assert_eq!(target.find_snippet_by_keyword("sig").await?.unwrap().content, "Regards");
+
+        // A filtered plain export only has the matching entries
+        let jsonl = temp_dir.path().join("work.jsonl");
+        let filter = ExportFilter { tag: Some("#work".into()), ..ExportFilter::default() };
+        assert_eq!(export(&source, &jsonl, &filter, false).await?.entries, 1);
+        assert_eq!(std::fs::read_to_string(&jsonl)?.lines().count(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,454 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+
+use crate::backup::{self, ExportFilter};
+use crate::config::Config;
+use crate::database::Database;
+use crate::history_sync;
+use crate::hooks::Hooks;
+use crate::import::{self, Format};
+use crate::plugins::Plugins;
+use crate::share;
+use crate::template::{self, TemplateContext};
+
+const USAGE: &str = "Usage: clippoo-daemon [COMMAND]
+
+Runs the clipboard daemon when no command is given.
+
+Commands:
+  list [--frecent] [N]
+                 Show the N most recent (or most used) entries (default 20)
+  edit [ID]      Edit an entry (default: the current one) in $EDITOR
+  edit --new [ID]
+                 Save the edited text as a new entry instead
+  tag ID TAG...  Add tags to an entry; tagged entries are never cleaned up
+  untag ID TAG...
+                 Remove tags from an entry
+  tagged TAG     Show all entries with a tag
+  clear          Remove all untagged entries from the history
+  expire ID DURATION|never
+                 Remove an entry after DURATION (e.g. 30s, 5m, 2h), or keep
+                 it again
+  import FORMAT [FILE]
+                 Add the history of another clipboard manager: copyq,
+                 cliphist, clipman, gpaste or klipper (default: its usual file)
+  export FILE [--archive] [--tag TAG] [--type TYPE] [--since YYYY-MM-DD]
+         [--no-snippets]
+                 Write the history and snippets as JSON Lines; --archive
+                 writes a .tar.gz that also holds the files of large entries
+  restore FILE   Add the entries and snippets of an export or backup,
+                 merging entries already in the history
+  sync [DIR]     Merge the history with other machines once, through DIR
+                 (default: history_sync.dir in the config)
+  share-key      Print a new random key for share.key
+  stack-next     Pop the pasted item off the paste stack; the daemon then
+                 puts the next one on the clipboard
+  stack-clear    Empty the paste stack
+  snippets list  Show all snippets
+  snippets add NAME [--folder F] [--keyword K]
+                 Save a snippet, reading its text from stdin
+  snippets get [--raw] KEYWORD
+                 Print the snippet with that keyword trigger, with its
+                 placeholders expanded unless --raw is given
+  snippets remove ID
+                 Delete a snippet
+  snippets export FILE
+                 Write all snippets to a JSON file
+  snippets import FILE
+                 Add snippets from a JSON file, replacing ones with the
+                 same folder and name
+  plugins        Show the loaded plugins and what they register
+  help           Show this message";
+
+/// Handle a one-shot command against the history database
+pub async fn run(command: &str, args: &[String]) -> Result<()> {
+    match command {
+        "list" => {
+            let frecent = args.iter().any(|arg| arg == "--frecent");
+            let limit = match args.iter().find(|arg| !arg.starts_with("--")) {
+                Some(n) => n.parse().map_err(|_| anyhow::anyhow!("Invalid count '{}'", n))?,
+                None => 20,
+            };
+
+            let db = Database::new().await?;
+            let tags = db.get_entry_tags().await?;
+            let entries = if frecent {
+                db.get_frecent_entries(limit).await?
+            } else {
+                db.get_recent_entries(limit).await?
+            };
+            for entry in entries {
+                let marker = if entry.is_default { "*" } else { " " };
+                let tags = tags.get(&entry.id).map(|tags| format!(" #{}", tags.join(" #"))).unwrap_or_default();
+                println!("{:>6} {} {}{}", entry.id, marker, preview(&entry.content), tags);
+            }
+            Ok(())
+        }
+        "tag" | "untag" => {
+            let id = args.first().ok_or_else(|| anyhow::anyhow!("Missing entry id\n\n{}", USAGE))?;
+            let id = id.parse().map_err(|_| anyhow::anyhow!("Invalid entry id '{}'", id))?;
+            if args.len() < 2 {
+                return Err(anyhow::anyhow!("Missing tag\n\n{}", USAGE));
+            }
+
+            let db = Database::new().await?;
+            db.get_entry(id).await?.ok_or_else(|| anyhow::anyhow!("No such clipboard entry"))?;
+            for tag in &args[1..] {
+                if command == "tag" {
+                    db.tag_entry(id, tag).await?;
+                } else {
+                    db.untag_entry(id, tag).await?;
+                }
+            }
+            Ok(())
+        }
+        "tagged" => {
+            let tag = args.first().ok_or_else(|| anyhow::anyhow!("Missing tag\n\n{}", USAGE))?;
+            for entry in Database::new().await?.entries_with_tag(tag).await? {
+                println!("{:>6} {}", entry.id, preview(&entry.content));
+            }
+            Ok(())
+        }
+        "clear" => {
+            let removed = Database::new().await?.clear_history().await?;
+            println!("Removed {} entries", removed);
+
+            Hooks::new(Config::load_or_default().hooks).on_clear().await;
+            Ok(())
+        }
+        "expire" => {
+            let id = args.first().ok_or_else(|| anyhow::anyhow!("Missing entry id\n\n{}", USAGE))?;
+            let id = id.parse().map_err(|_| anyhow::anyhow!("Invalid entry id '{}'", id))?;
+            let duration = args.get(1).ok_or_else(|| anyhow::anyhow!("Missing duration\n\n{}", USAGE))?;
+
+            let db = Database::new().await?;
+            if duration == "never" {
+                db.set_expiry(id, None).await?;
+                println!("Entry {} no longer expires", id);
+            } else {
+                let seconds = parse_duration(duration).ok_or_else(|| anyhow::anyhow!("Invalid duration '{}'", duration))?;
+                db.set_expiry(id, Some(chrono::Utc::now() + chrono::Duration::seconds(seconds))).await?;
+                println!("Entry {} expires in {}", id, duration);
+            }
+            Ok(())
+        }
+        "edit" => edit_entry(args).await,
+        "import" => {
+            let name = args.first().ok_or_else(|| anyhow::anyhow!("Missing format\n\n{}", USAGE))?;
+            let format = Format::from_name(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown format '{}'\n\n{}", name, USAGE))?;
+            let path = match args.get(1) {
+                Some(path) => path.into(),
+                None => format.default_path().ok_or_else(|| anyhow::anyhow!("Could not find the {} history", name))?,
+            };
+
+            let entries = import::read(format, &path)?;
+            let config = Config::load_or_default();
+            let mut db = Database::new().await?.with_blob_threshold(config.storage.blob_threshold);
+            db.set_dedup_policy(config.dedup).await?;
+
+            let count = entries.len();
+            let added = db.import_entries(entries).await?;
+            println!("Read {} entries from {}, {} of them new", count, path.display(), added);
+            Ok(())
+        }
+        "export" => {
+            let path = args.first().ok_or_else(|| anyhow::anyhow!("Missing file name\n\n{}", USAGE))?;
+            let since = match option_value(args, "--since") {
+                Some(date) => Some(
+                    chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                        .map_err(|_| anyhow::anyhow!("Invalid date '{}'", date))?
+                        .and_hms_opt(0, 0, 0)
+                        .unwrap_or_default()
+                        .and_utc(),
+                ),
+                None => None,
+            };
+            let filter = ExportFilter {
+                tag: option_value(args, "--tag"),
+                content_type: option_value(args, "--type"),
+                since,
+                snippets: !args.iter().any(|arg| arg == "--no-snippets"),
+            };
+            let archive = args.iter().any(|arg| arg == "--archive");
+
+            let db = Database::new().await?;
+            let summary = backup::export(&db, path.as_ref(), &filter, archive).await?;
+            println!("Exported {} entries and {} snippets to {}", summary.entries, summary.snippets, path);
+            Ok(())
+        }
+        "restore" => {
+            let path = args.first().ok_or_else(|| anyhow::anyhow!("Missing file name\n\n{}", USAGE))?;
+            let config = Config::load_or_default();
+            let mut db = Database::new().await?.with_blob_threshold(config.storage.blob_threshold);
+            db.set_dedup_policy(config.dedup).await?;
+
+            let summary = backup::restore(&db, path.as_ref()).await?;
+            println!("Restored {} entries and {} snippets from {}", summary.entries, summary.snippets, path);
+            Ok(())
+        }
+        "sync" => {
+            let config = Config::load_or_default();
+            let dir = match args.first() {
+                Some(dir) => dir.into(),
+                None => config.history_sync.dir.ok_or_else(|| anyhow::anyhow!("No sync directory configured\n\n{}", USAGE))?,
+            };
+            let mut db = Database::new().await?.with_blob_threshold(config.storage.blob_threshold);
+            db.set_dedup_policy(config.dedup).await?;
+
+            let db = tokio::sync::Mutex::new(db);
+            let (sent, received) = history_sync::sync_once(&db, &history_sync::expand_home(&dir)).await?;
+            println!("Sent {} changes, applied {} from other machines", sent, received);
+            Ok(())
+        }
+        "share-key" => {
+            println!("{}", share::generate_key()?);
+            Ok(())
+        }
+        "stack-next" => {
+            let db = Database::new().await?;
+            db.pop_stack().await?;
+
+            let remaining = db.get_stack_entries().await?;
+            match remaining.first() {
+                Some(next) => println!("{} left on the paste stack, next: {}", remaining.len(), preview(&next.content)),
+                None => println!("Paste stack is empty"),
+            }
+            Ok(())
+        }
+        "stack-clear" => {
+            Database::new().await?.clear_stack().await?;
+            println!("Paste stack cleared");
+            Ok(())
+        }
+        "snippets" => snippets(args).await,
+        "plugins" => {
+            let plugins = Plugins::load(&Config::load_or_default().plugins, None);
+            let loaded = plugins.describe();
+            if loaded.is_empty() {
+                println!("No plugins loaded");
+            }
+            for (name, registered) in loaded {
+                println!("{}: {}", name, if registered.is_empty() { "nothing registered".to_string() } else { registered.join(", ") });
+            }
+            Ok(())
+        }
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(anyhow::anyhow!("Unknown command '{}'\n\n{}", other, USAGE)),
+    }
+}
+
+async fn edit_entry(args: &[String]) -> Result<()> {
+    let as_new = args.iter().any(|arg| arg == "--new");
+    let id = args.iter().find(|arg| !arg.starts_with("--"));
+
+    let blob_threshold = Config::load_or_default().storage.blob_threshold;
+    let db = Database::new().await?.with_blob_threshold(blob_threshold);
+    let entry = match id {
+        Some(id) => {
+            let id = id.parse().map_err(|_| anyhow::anyhow!("Invalid entry id '{}'", id))?;
+            db.get_entry(id).await?
+        }
+        None => db.get_default_entry().await?,
+    }
+    .ok_or_else(|| anyhow::anyhow!("No such clipboard entry"))?;
+
+    let edited = edit_in_editor(&entry.content)?;
+    if edited == entry.content {
+        println!("No changes");
+        return Ok(());
+    }
+
+    if as_new {
+        let id = db.add_entry(edited).await?;
+        println!("Saved as new entry {}", id);
+    } else {
+        let id = db.update_entry(entry.id, edited).await?;
+        println!("Updated entry {}", id);
+    }
+    Ok(())
+}
+
+/// Open `content` in $VISUAL/$EDITOR and return the saved text
+fn edit_in_editor(content: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // Created exclusively and readable only by the user, since entries are
+    // often secrets; the file is removed when `file` is dropped
+    let mut file = tempfile::Builder::new().prefix("clippoo-edit-").suffix(".txt").tempfile()?;
+    file.write_all(content.as_bytes())?;
+    file.flush()?;
+    let path = file.path().to_path_buf();
+
+    // $EDITOR may carry arguments, e.g. "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or_else(|| anyhow::anyhow!("$EDITOR is empty"))?;
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(&path)
+        .status();
+
+    let edited = std::fs::read_to_string(&path);
+    drop(file);
+
+    let status = status.map_err(|e| anyhow::anyhow!("Failed to run editor '{}': {}", editor, e))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("Editor exited with {}, entry left unchanged", status));
+    }
+
+    let mut edited = edited?;
+    // Most editors end the file with a newline the original didn't have
+    if !content.ends_with('\n') && edited.ends_with('\n') {
+        edited.pop();
+    }
+    Ok(edited)
+}
+
+/// Snippet as stored in import/export files
+#[derive(Serialize, Deserialize)]
+struct SnippetFile {
+    name: String,
+    #[serde(default)]
+    folder: String,
+    #[serde(default)]
+    keyword: Option<String>,
+    content: String,
+}
+
+async fn snippets(args: &[String]) -> Result<()> {
+    let db = Database::new().await?;
+
+    match args.first().map(String::as_str) {
+        Some("list") | None => {
+            for snippet in db.get_snippets().await? {
+                let keyword = snippet.keyword.map(|k| format!(" [{}]", k)).unwrap_or_default();
+                println!("{:>6} {}/{}{}", snippet.id, snippet.folder, snippet.name, keyword);
+            }
+            Ok(())
+        }
+        Some("add") => {
+            let name = args.get(1).ok_or_else(|| anyhow::anyhow!("Missing snippet name\n\n{}", USAGE))?;
+            let folder = option_value(args, "--folder").unwrap_or_default();
+            let keyword = option_value(args, "--keyword");
+
+            let mut content = String::new();
+            std::io::stdin().read_to_string(&mut content)?;
+            if content.is_empty() {
+                return Err(anyhow::anyhow!("No snippet text on stdin"));
+            }
+
+            let id = db.save_snippet(&folder, name, keyword.as_deref(), &content).await?;
+            println!("Saved snippet {}", id);
+            Ok(())
+        }
+        Some("get") => {
+            let raw = args.iter().any(|arg| arg == "--raw");
+            let keyword = args[1..]
+                .iter()
+                .find(|arg| !arg.starts_with("--"))
+                .ok_or_else(|| anyhow::anyhow!("Missing keyword\n\n{}", USAGE))?;
+            let snippet = db
+                .find_snippet_by_keyword(keyword)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("No snippet with keyword '{}'", keyword))?;
+
+            if raw {
+                print!("{}", snippet.content);
+                return Ok(());
+            }
+
+            let clipboard = db.get_default_entry().await?.map(|entry| entry.content).unwrap_or_default();
+            let inputs = ask_inputs(&template::input_labels(&snippet.content))?;
+            print!("{}", template::expand(&snippet.content, &TemplateContext { clipboard: &clipboard, inputs: &inputs }));
+            Ok(())
+        }
+        Some("remove") => {
+            let id = args.get(1).ok_or_else(|| anyhow::anyhow!("Missing snippet id\n\n{}", USAGE))?;
+            let id = id.parse().map_err(|_| anyhow::anyhow!("Invalid snippet id '{}'", id))?;
+            db.delete_snippet(id).await?;
+            println!("Removed snippet {}", id);
+            Ok(())
+        }
+        Some("export") => {
+            let path = args.get(1).ok_or_else(|| anyhow::anyhow!("Missing file name\n\n{}", USAGE))?;
+            let snippets: Vec<SnippetFile> = db
+                .get_snippets()
+                .await?
+                .into_iter()
+                .map(|s| SnippetFile { name: s.name, folder: s.folder, keyword: s.keyword, content: s.content })
+                .collect();
+
+            std::fs::write(path, serde_json::to_string_pretty(&snippets)?)?;
+            println!("Exported {} snippets to {}", snippets.len(), path);
+            Ok(())
+        }
+        Some("import") => {
+            let path = args.get(1).ok_or_else(|| anyhow::anyhow!("Missing file name\n\n{}", USAGE))?;
+            let snippets: Vec<SnippetFile> = serde_json::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|e| anyhow::anyhow!("Invalid snippets file {}: {}", path, e))?;
+
+            db.save_snippets(snippets.iter().map(|s| (s.folder.as_str(), s.name.as_str(), s.keyword.as_deref(), s.content.as_str())))
+                .await
+                .map_err(|e| anyhow::anyhow!("Nothing imported from {}: {}", path, e))?;
+            println!("Imported {} snippets from {}", snippets.len(), path);
+            Ok(())
+        }
+        Some(other) => Err(anyhow::anyhow!("Unknown snippets command '{}'\n\n{}", other, USAGE)),
+    }
+}
+
+/// Ask for each `{{input:Label}}` value on the terminal
+fn ask_inputs(labels: &[String]) -> Result<HashMap<String, String>> {
+    let mut inputs = HashMap::new();
+    let mut lines = std::io::stdin().lock().lines();
+
+    for label in labels {
+        // Prompt on stderr so stdout only carries the expanded snippet
+        eprint!("{}: ", label);
+        std::io::stderr().flush()?;
+
+        let value = lines.next().transpose()?.unwrap_or_default();
+        inputs.insert(label.clone(), value);
+    }
+    Ok(inputs)
+}
+
+/// Value following `--name` in the arguments
+fn option_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
+
+/// Seconds in a duration like "90", "30s", "5m" or "2h"
+fn parse_duration(text: &str) -> Option<i64> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => text.split_at(at),
+        None => (text, "s"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    number.parse::<i64>().ok()?.checked_mul(scale)
+}
+
+fn preview(content: &str) -> String {
+    let line = content.lines().next().unwrap_or_default();
+    if line.chars().count() > 60 {
+        format!("{}...", line.chars().take(60).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
@@ -1,26 +1,99 @@
 use anyhow::Result;
-use arboard::Clipboard;
+use arboard::{Clipboard, GetExtLinux, LinuxClipboardKind, SetExtLinux};
 use log::{debug, error, info, warn};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration};
 
-use crate::database::Database;
+use crate::classify::{classify, sensitive_class, ContentType};
+use crate::config::{Config, ExpiryConfig, KeepAlive, SyncConfig};
+use crate::database::{ClipboardEntry, Database, EntrySource, Selection};
+use crate::hooks::Hooks;
+use crate::plugins::{HistoryFn, Plugins};
+use crate::share::Share;
+use crate::source;
+
+/// The PRIMARY selection as last seen, waiting out the debounce
+struct PrimaryState {
+    seen: Option<String>,
+    changed_at: Instant,
+    /// The last selection that was stored or synced
+    stored: Option<String>,
+}
+
+/// Type password managers add to copies that must not be kept around
+const CONCEALED_TYPE: &str = "x-kde-passwordManagerHint";
+
+/// X11 targets that plain text is offered as, besides text/plain
+const TEXT_TARGETS: &[&str] = &["UTF8_STRING", "STRING", "TEXT", "COMPOUND_TEXT"];
+
+/// X11 targets every selection owner offers, whatever the content
+const META_TARGETS: &[&str] = &["TARGETS", "TIMESTAMP", "MULTIPLE", "SAVE_TARGETS", "DELETE", "INCR"];
+
+/// Who serves the copy on the clipboard
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Owner {
+    /// The popup, which exits right after an entry is picked
+    Popup,
+    /// The app in the focused window when the copy was seen
+    App(u32),
+    /// The daemon itself, after taking a copy over or offering an entry again
+    Daemon,
+    /// An app that can't be told apart from one clearing the clipboard
+    Unknown,
+}
+
+/// What is known about the copy on the clipboard, to decide whether the
+/// daemon may serve it in place of its owner
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LastCopy {
+    owner: Owner,
+    /// Marked as a password by the app it came from, or of a sensitive class
+    /// set to expire
+    sensitive: bool,
+    /// Offered as plain text only, so serving it as text loses nothing
+    text_only: bool,
+}
+
+impl LastCopy {
+    fn served() -> Self {
+        Self { owner: Owner::Daemon, sensitive: false, text_only: true }
+    }
+}
 
 pub struct ClipboardWatcher {
     db: Arc<Mutex<Database>>,
+    config: Config,
+    hooks: Hooks,
+    plugins: Plugins,
     last_content: Arc<Mutex<Option<String>>>,
+    /// The copy the clipboard holds, until it is found emptied
+    last_copy: Mutex<Option<LastCopy>>,
+    stack_head: Arc<Mutex<Option<i64>>>,
+    share: Option<Share>,
 }
 
 impl ClipboardWatcher {
-    pub fn new(db: Arc<Mutex<Database>>) -> Self {
+    pub fn new(db: Arc<Mutex<Database>>, config: Config) -> Self {
         Self {
+            plugins: Plugins::load(&config.plugins, Some(plugin_history(db.clone()))),
             db,
+            hooks: Hooks::new(config.hooks.clone()),
+            config,
             last_content: Arc::new(Mutex::new(None)),
+            last_copy: Mutex::new(None),
+            stack_head: Arc::new(Mutex::new(None)),
+            share: None,
         }
     }
 
+    /// Send new copies to the daemons on other machines
+    pub fn with_share(mut self, share: Share) -> Self {
+        self.share = Some(share);
+        self
+    }
+
     pub async fn start_monitoring(&self) -> Result<()> {
         info!("Starting efficient clipboard monitoring with arboard");
 
@@ -33,6 +106,12 @@ impl ClipboardWatcher {
             }
         };
 
+        if self.config.clipboard.restore_on_start {
+            if let Err(e) = self.restore_default(&mut clipboard).await {
+                warn!("Failed to restore the clipboard: {}", e);
+            }
+        }
+
         // Get initial clipboard content
         match clipboard.get_text() {
             Ok(content) => {
@@ -48,8 +127,25 @@ impl ClipboardWatcher {
         // Main monitoring loop with adaptive polling
         let mut poll_interval = Duration::from_millis(100); // Start with fast polling
         let mut last_change = Instant::now();
+        let mut primary = PrimaryState { seen: None, changed_at: Instant::now(), stored: None };
+        let mut last_purge = Instant::now();
         
         loop {
+            if last_purge.elapsed() >= Duration::from_secs(1) {
+                if let Err(e) = self.purge_expired(&mut clipboard).await {
+                    warn!("Failed to remove expired entries: {}", e);
+                }
+                last_purge = Instant::now();
+            }
+
+            if let Err(e) = self.sync_paste_stack(&mut clipboard).await {
+                warn!("Failed to update clipboard from paste stack: {}", e);
+            }
+
+            if self.config.primary.enabled || self.config.sync.direction.to_clipboard() {
+                self.poll_primary(&mut clipboard, &mut primary).await;
+            }
+
             match clipboard.get_text() {
                 Ok(content) => {
                     if !content.trim().is_empty() {
@@ -58,13 +154,24 @@ impl ClipboardWatcher {
                         if last.as_ref() != Some(&content) {
                             // Content changed
                             debug!("New clipboard content detected: {} bytes", content.len());
+
+                            sync_selection(&mut clipboard, LinuxClipboardKind::Primary, &content, &self.config.sync);
+                            
+                            let source = source::detect(Selection::Clipboard).await;
+                            *self.last_copy.lock().await = Some(self.inspect_copy(&content, &source).await);
                             
                             // Update database
-                            if let Err(e) = self.db.lock().await.add_entry(content.clone()).await {
+                            if let Err(e) = self.store_entry(&content, source).await {
                                 error!("Failed to add clipboard entry: {}", e);
                             } else {
                                 info!("Added new clipboard entry to database");
                             }
+
+                            if self.config.clipboard.keep_alive == KeepAlive::Always {
+                                if let Err(e) = self.take_over(&mut clipboard, &content).await {
+                                    warn!("Failed to take over the clipboard: {}", e);
+                                }
+                            }
                             
                             *last = Some(content);
                             last_change = Instant::now();
@@ -97,6 +204,10 @@ impl ClipboardWatcher {
                     if error_str.contains("empty") || error_str.contains("Empty") {
                         debug!("Clipboard is empty");
                         poll_interval = Duration::from_millis(500);
+
+                        if let Err(e) = self.keep_alive(&mut clipboard).await {
+                            warn!("Failed to restore the clipboard: {}", e);
+                        }
                     } else if error_str.contains("not available") || error_str.contains("format") {
                         debug!("Clipboard content not available (non-text data)");
                         poll_interval = Duration::from_millis(500);
@@ -128,8 +239,11 @@ impl ClipboardWatcher {
         if last.as_ref() != Some(&content) {
             debug!("Processing initial clipboard content: {} bytes", content.len());
             
+            let source = source::detect(Selection::Clipboard).await;
+            *self.last_copy.lock().await = Some(self.inspect_copy(&content, &source).await);
+            
             // Update database
-            if let Err(e) = self.db.lock().await.add_entry(content.clone()).await {
+            if let Err(e) = self.store_entry(&content, source).await {
                 error!("Failed to add clipboard entry: {}", e);
             } else {
                 info!("Added initial clipboard entry to database");
@@ -138,6 +252,387 @@ impl ClipboardWatcher {
             *last = Some(content);
         }
     }
+
+    /// Who owns a new copy and whether the daemon may serve it. A copy of
+    /// the default entry was picked in the popup, which marks it first.
+    async fn inspect_copy(&self, content: &str, source: &EntrySource) -> LastCopy {
+        let picked = match self.db.lock().await.get_default_entry().await {
+            Ok(entry) => entry.is_some_and(|entry| entry.content == content),
+            Err(e) => {
+                warn!("Failed to read the default entry: {}", e);
+                false
+            }
+        };
+
+        LastCopy {
+            owner: if picked { Owner::Popup } else { source.pid.map_or(Owner::Unknown, Owner::App) },
+            sensitive: is_sensitive(content, &source.mime_types, &self.config.expiry),
+            text_only: offers_only_text(&source.mime_types),
+        }
+    }
+
+    /// Put the default entry back on the clipboard once it has been emptied,
+    /// which on Wayland happens when the app that was copied from exits. An
+    /// app that empties it while still running, like a password manager
+    /// clearing a password, gets its way.
+    async fn keep_alive(&self, clipboard: &mut Clipboard) -> Result<()> {
+        if self.config.clipboard.keep_alive == KeepAlive::Off || !is_emptied(clipboard) {
+            return Ok(());
+        }
+
+        // Decided once per copy, so an owner that clears and then exits
+        // doesn't get the copy back later
+        let Some(copy) = self.last_copy.lock().await.take() else {
+            return Ok(());
+        };
+        if !should_offer_again(copy, process_running) {
+            debug!("Clipboard was emptied by its owner {:?}, leaving it empty", copy.owner);
+            return Ok(());
+        }
+
+        let mut last = self.last_content.lock().await;
+        let Some(entry) = self.db.lock().await.get_default_entry().await? else {
+            return Ok(());
+        };
+
+        clipboard.set_text(entry.content.clone())
+            .map_err(|e| anyhow::anyhow!("Failed to set clipboard content: {}", e))?;
+        *last = Some(entry.content);
+        *self.last_copy.lock().await = Some(LastCopy::served());
+        info!("Clipboard was emptied, offering entry {} again", entry.id);
+        Ok(())
+    }
+
+    /// Remove the entries whose time to live is up, and the clipboard content
+    /// if it came from one of them. The content is still remembered as seen,
+    /// so it is not stored again.
+    async fn purge_expired(&self, clipboard: &mut Clipboard) -> Result<()> {
+        let expired = self.db.lock().await.purge_expired().await?;
+        for entry in &expired {
+            info!("Entry {} expired", entry.id);
+        }
+
+        if expired.iter().any(|entry| entry.is_default) {
+            clipboard.clear()
+                .map_err(|e| anyhow::anyhow!("Failed to clear the clipboard: {}", e))?;
+            *self.last_copy.lock().await = None;
+            info!("Cleared the clipboard, its entry expired");
+        }
+        Ok(())
+    }
+
+    /// Put the default entry back on the clipboard after a reboot. Content
+    /// copied before the daemon started is kept.
+    async fn restore_default(&self, clipboard: &mut Clipboard) -> Result<()> {
+        if clipboard.get_text().is_ok() || !is_emptied(clipboard) {
+            return Ok(());
+        }
+        let Some(entry) = self.db.lock().await.get_default_entry().await? else {
+            return Ok(());
+        };
+        if is_sensitive(&entry.content, &[], &self.config.expiry) {
+            debug!("Not restoring sensitive entry {}", entry.id);
+            return Ok(());
+        }
+
+        clipboard.set_text(entry.content.clone())
+            .map_err(|e| anyhow::anyhow!("Failed to set clipboard content: {}", e))?;
+        *self.last_content.lock().await = Some(entry.content);
+        *self.last_copy.lock().await = Some(LastCopy::served());
+        info!("Restored entry {} to the clipboard", entry.id);
+        Ok(())
+    }
+
+    /// Serve a copy from the daemon, so it outlives the app it came from.
+    /// The daemon can only serve text, so copies also offered in richer
+    /// formats stay with the app, as do sensitive copies and ones that a
+    /// filter or hook dropped or rewrote.
+    async fn take_over(&self, clipboard: &mut Clipboard, content: &str) -> Result<()> {
+        let mut last_copy = self.last_copy.lock().await;
+        if !last_copy.is_some_and(should_take_over) {
+            debug!("Leaving the copy to its app: {:?}", *last_copy);
+            return Ok(());
+        }
+
+        let stored = self.db.lock().await.get_default_entry().await?;
+        if stored.is_some_and(|entry| entry.content == content) {
+            clipboard.set_text(content.to_string())
+                .map_err(|e| anyhow::anyhow!("Failed to set clipboard content: {}", e))?;
+            *last_copy = Some(LastCopy::served());
+            debug!("Took over the clipboard");
+        }
+        Ok(())
+    }
+
+    /// Store the PRIMARY selection and sync it to CLIPBOARD once it has stopped
+    /// changing, so that drag-selecting doesn't act on every intermediate selection
+    async fn poll_primary(&self, clipboard: &mut Clipboard, primary: &mut PrimaryState) {
+        let text = match clipboard.get().clipboard(LinuxClipboardKind::Primary).text() {
+            Ok(text) if !text.trim().is_empty() => text,
+            _ => return,
+        };
+
+        if primary.seen.as_ref() != Some(&text) {
+            primary.seen = Some(text);
+            primary.changed_at = Instant::now();
+            return;
+        }
+        let debounce = Duration::from_millis(self.config.primary.debounce_ms);
+        if primary.stored.as_ref() == Some(&text) || primary.changed_at.elapsed() < debounce {
+            return;
+        }
+
+        // Selected text that was then copied is already stored as a clipboard entry
+        let mut last = self.last_content.lock().await;
+        if last.as_ref() != Some(&text) {
+            if self.config.primary.enabled {
+                match self.store_entry(&text, source::detect(Selection::Primary).await).await {
+                    Ok(()) => info!("Added primary selection entry to database"),
+                    Err(e) => error!("Failed to add primary selection entry: {}", e),
+                }
+            }
+
+            // Marked as seen, so the selection isn't stored again as a copy
+            if sync_selection(clipboard, LinuxClipboardKind::Clipboard, &text, &self.config.sync) {
+                *last = Some(text.clone());
+                *self.last_copy.lock().await = Some(LastCopy::served());
+            }
+        }
+        primary.stored = Some(text);
+    }
+
+    async fn store_entry(&self, content: &str, source: EntrySource) -> Result<()> {
+        if content.len() > self.config.storage.max_size {
+            info!("Not storing {} bytes, over the storage.max_size limit", content.len());
+            return Ok(());
+        }
+
+        debug!("Clipboard content source: {:?}", source);
+        let selection = source.selection;
+
+        // Plugin filters, then capture hooks, see the entry as it would be
+        // stored and may drop or rewrite it
+        let mut entry = ClipboardEntry {
+            id: 0,
+            content: content.to_string(),
+            timestamp: chrono::Utc::now(),
+            is_default: selection == Selection::Clipboard,
+            source_app: source.app.clone(),
+            window_title: source.window_title.clone(),
+            source_url: source.url.clone(),
+            content_type: None,
+            language: None,
+            selection,
+            blob: None,
+            expires_at: None,
+            origin_host: None,
+        };
+        let mut content_type = self.classify_entry(&mut entry);
+
+        let Some(content) = self.plugins.filter(entry.clone()) else {
+            return Ok(());
+        };
+        if content != entry.content {
+            entry.content = content;
+            content_type = self.classify_entry(&mut entry);
+        }
+
+        let Some(content) = self.hooks.on_capture(entry.clone()).await else {
+            return Ok(());
+        };
+        if content != entry.content {
+            entry.content = content;
+            content_type = self.classify_entry(&mut entry);
+        }
+
+        let db = self.db.lock().await;
+        let id = db.add_entry_with_source(entry.content.clone(), &source).await?;
+        db.set_content_type(id, content_type.name(), entry.language.as_deref()).await?;
+
+        if let Some(seconds) = self.config.expiry.time_to_live(&entry.content, content_type.name()) {
+            let expires_at = chrono::Utc::now() + chrono::Duration::seconds(seconds as i64);
+            db.set_expiry(id, Some(expires_at)).await?;
+            entry.expires_at = Some(expires_at);
+            debug!("Entry {} expires in {} seconds", id, seconds);
+        }
+
+        // The stack head becomes the default entry, which selections never are
+        if self.config.paste_stack.collect_copies && selection == Selection::Clipboard {
+            db.push_stack(id).await?;
+            // The copy took the head's place on the clipboard
+            *self.stack_head.lock().await = None;
+            debug!("Pushed entry {} onto the paste stack", id);
+        }
+
+        // Selections are too fleeting to send around, and passwords and codes
+        // must stay on this machine
+        let shared = selection == Selection::Clipboard && may_share(&entry.content, &source.mime_types);
+        if let Some(share) = self.share.as_ref().filter(|_| shared) {
+            entry.content_type = Some(content_type.name().to_string());
+            share.publish(&entry);
+        }
+
+        Ok(())
+    }
+
+    /// Classify with the plugin classifiers, falling back to the built-in one
+    fn classify_entry(&self, entry: &mut ClipboardEntry) -> ContentType {
+        entry.content_type = None;
+        entry.language = None;
+
+        let (content_type, language) = self.plugins.classify(entry).unwrap_or_else(|| {
+            let classification = classify(&entry.content);
+            (classification.content_type, classification.language.map(String::from))
+        });
+        entry.content_type = Some(content_type.name().to_string());
+        entry.language = language;
+        content_type
+    }
+
+    /// Put the head of the paste stack on the clipboard whenever it changes,
+    /// i.e. when items are stacked from the popup or `clippoo-daemon stack-next`
+    /// pops the one that was just pasted.
+    async fn sync_paste_stack(&self, clipboard: &mut Clipboard) -> Result<()> {
+        let Some(entry) = self.next_stack_entry().await? else {
+            return Ok(());
+        };
+
+        let mut last = self.last_content.lock().await;
+        if last.as_ref() != Some(&entry.content) {
+            clipboard.set_text(entry.content.clone())
+                .map_err(|e| anyhow::anyhow!("Failed to set clipboard content: {}", e))?;
+            *last = Some(entry.content);
+            *self.last_copy.lock().await = Some(LastCopy::served());
+        }
+        info!("Paste stack advanced to entry {}", entry.id);
+        Ok(())
+    }
+
+    /// The head of the paste stack, made the default entry, if it has to go
+    /// on the clipboard: it moved, or a collected copy took its place.
+    async fn next_stack_entry(&self) -> Result<Option<ClipboardEntry>> {
+        let db = self.db.lock().await;
+        let head = db.get_stack_head().await?;
+        let mut stack_head = self.stack_head.lock().await;
+
+        let position = head.as_ref().map(|(position, _)| *position);
+        if *stack_head == position {
+            return Ok(None);
+        }
+        *stack_head = position;
+
+        let Some((_, entry)) = head else {
+            return Ok(None);
+        };
+        db.set_default_entry(entry.id).await?;
+        Ok(Some(entry))
+    }
+}
+
+/// Whether the clipboard holds nothing at all, rather than only no text
+fn is_emptied(clipboard: &mut Clipboard) -> bool {
+    nothing_offered(&clipboard.get_image(), &clipboard.get().file_list())
+}
+
+fn nothing_offered<T, U>(image: &Result<T, arboard::Error>, files: &Result<U, arboard::Error>) -> bool {
+    matches!(image, Err(arboard::Error::ContentNotAvailable)) && matches!(files, Err(arboard::Error::ContentNotAvailable))
+}
+
+/// Whether an emptied clipboard gets the default entry back: only when the
+/// app that served the copy has exited. A running owner, or the daemon
+/// itself, was emptied on purpose, and an unknown owner might have been.
+fn should_offer_again(copy: LastCopy, running: impl Fn(u32) -> bool) -> bool {
+    if copy.sensitive {
+        return false;
+    }
+    match copy.owner {
+        Owner::Popup => true,
+        Owner::App(pid) => !running(pid),
+        Owner::Daemon | Owner::Unknown => false,
+    }
+}
+
+/// Whether the daemon may serve a new copy itself with `keep_alive: always`
+fn should_take_over(copy: LastCopy) -> bool {
+    copy.text_only && !copy.sensitive && copy.owner != Owner::Daemon
+}
+
+/// Whether a copy must not outlive its app: marked as a password, or of a
+/// sensitive class ("otp", "secret") the user set to expire. Other content
+/// that merely looks like a code, such as a year, is kept alive as usual.
+fn is_sensitive(content: &str, mime_types: &[String], expiry: &ExpiryConfig) -> bool {
+    is_concealed(mime_types) || sensitive_class(content).is_some_and(|class| expiry.after.contains_key(class))
+}
+
+/// Whether a copy may be sent to other machines: not marked as a password
+/// and not looking like a one-time code or secret, whatever the expiry
+fn may_share(content: &str, mime_types: &[String]) -> bool {
+    !is_concealed(mime_types) && sensitive_class(content).is_none()
+}
+
+fn is_concealed(mime_types: &[String]) -> bool {
+    mime_types.iter().any(|mime| mime == CONCEALED_TYPE)
+}
+
+/// Whether a copy offered as these MIME types is plain text and nothing more.
+/// Nothing is known when no types could be listed.
+fn offers_only_text(types: &[String]) -> bool {
+    !types.is_empty()
+        && types.iter().all(|mime| {
+            mime.starts_with("text/plain") || TEXT_TARGETS.contains(&mime.as_str()) || META_TARGETS.contains(&mime.as_str())
+        })
+}
+
+fn process_running(pid: u32) -> bool {
+    std::path::Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Whether `text` from the other selection should be put in `into`: the sync
+/// direction includes it, selected text is long enough for CLIPBOARD, and with
+/// `text_only` it doesn't hold an image
+fn should_sync(sync: &SyncConfig, into: LinuxClipboardKind, text: &str, holds_image: impl FnOnce() -> bool) -> bool {
+    let wanted = match into {
+        LinuxClipboardKind::Clipboard => sync.direction.to_clipboard() && text.chars().count() >= sync.min_length,
+        LinuxClipboardKind::Primary => sync.direction.to_primary(),
+        LinuxClipboardKind::Secondary => false,
+    };
+    wanted && !(sync.text_only && holds_image())
+}
+
+/// Put `text` in another selection when [`should_sync`] allows it. Returns
+/// whether the selection now holds `text`.
+fn sync_selection(clipboard: &mut Clipboard, kind: LinuxClipboardKind, text: &str, sync: &SyncConfig) -> bool {
+    if !should_sync(sync, kind, text, || clipboard.get().clipboard(kind).image().is_ok()) {
+        return false;
+    }
+    if clipboard.get().clipboard(kind).text().is_ok_and(|current| current == text) {
+        return true;
+    }
+
+    match clipboard.set().clipboard(kind).text(text.to_string()) {
+        Ok(()) => {
+            debug!("Synced {} bytes to {:?}", text.len(), kind);
+            true
+        }
+        Err(e) => {
+            warn!("Failed to sync selection to {:?}: {}", kind, e);
+            false
+        }
+    }
+}
+
+/// Plugins run synchronously on the watcher's task, so their history reads
+/// block on the database
+fn plugin_history(db: Arc<Mutex<Database>>) -> HistoryFn {
+    Arc::new(move |limit| {
+        let entries = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async { db.lock().await.get_recent_entries(limit).await })
+        });
+        entries.unwrap_or_else(|e| {
+            warn!("Failed to read history for a plugin: {}", e);
+            Vec::new()
+        })
+    })
 }
 
 #[cfg(test)]
@@ -155,4 +650,129 @@ mod tests {
         // This test always passes - it's just informational
         assert!(true);
     }
+
+    #[tokio::test]
+    async fn test_collected_copies_paste_in_order() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let db = Arc::new(Mutex::new(Database::open(&temp_dir.path().join("clipboard.db")).await?));
+        let mut config = Config::default();
+        config.paste_stack.collect_copies = true;
+        let watcher = ClipboardWatcher::new(db.clone(), config);
+
+        // Each copy lands on the clipboard, then the stack's head goes back
+        let mut clipboard = String::new();
+        for content in ["first", "second", "third"] {
+            clipboard = content.to_string();
+            watcher.store_entry(content, EntrySource::default()).await?;
+            if let Some(entry) = watcher.next_stack_entry().await? {
+                clipboard = entry.content;
+            }
+        }
+
+        let mut pasted = Vec::new();
+        while !clipboard.is_empty() {
+            pasted.push(std::mem::take(&mut clipboard));
+            db.lock().await.pop_stack().await?;
+            if let Some(entry) = watcher.next_stack_entry().await? {
+                clipboard = entry.content;
+            }
+        }
+        assert_eq!(pasted, ["first", "second", "third"]);
+
+        // Selections are not collected
+        let selected = EntrySource { selection: Selection::Primary, ..EntrySource::default() };
+        watcher.store_entry("selected", selected).await?;
+        assert!(db.lock().await.get_stack_entries().await?.is_empty());
+        assert!(watcher.next_stack_entry().await?.is_none());
+        Ok(())
+    }
+
+    fn copy(owner: Owner) -> LastCopy {
+        LastCopy { owner, sensitive: false, text_only: true }
+    }
+
+    #[test]
+    fn test_emptied_clipboard() {
+        let missing: Result<(), arboard::Error> = Err(arboard::Error::ContentNotAvailable);
+        assert!(nothing_offered(&missing, &missing));
+        assert!(!nothing_offered(&Ok(()), &missing));
+        assert!(!nothing_offered(&missing, &Ok(())));
+        assert!(!nothing_offered(&Err::<(), _>(arboard::Error::ClipboardOccupied), &missing));
+    }
+
+    #[test]
+    fn test_offer_again_only_after_the_owner_exited() {
+        let running = |pid| pid == 42;
+        assert!(should_offer_again(copy(Owner::Popup), running));
+        assert!(should_offer_again(copy(Owner::App(7)), running));
+
+        // Cleared on purpose, or possibly so
+        assert!(!should_offer_again(copy(Owner::App(42)), running));
+        assert!(!should_offer_again(copy(Owner::Daemon), running));
+        assert!(!should_offer_again(copy(Owner::Unknown), running));
+        assert!(!should_offer_again(LastCopy { sensitive: true, ..copy(Owner::App(7)) }, running));
+    }
+
+    #[test]
+    fn test_sensitive_copies() {
+        let concealed = vec![CONCEALED_TYPE.to_string(), "text/plain".to_string()];
+        let mut expiry = ExpiryConfig::default();
+        assert!(is_sensitive("hunter2", &concealed, &expiry));
+        assert!(!is_sensitive("2024", &[], &expiry));
+        assert!(!is_sensitive("482913", &[], &expiry));
+
+        expiry.after.insert("otp".to_string(), 60);
+        assert!(is_sensitive("482913", &[], &expiry));
+        assert!(!is_sensitive("ghp_a1B2c3D4e5F6g7H8i9J0", &[], &expiry));
+    }
+
+    #[test]
+    fn test_sensitive_copies_stay_here() {
+        let plain = vec!["text/plain".to_string()];
+        let concealed = vec![CONCEALED_TYPE.to_string(), "text/plain".to_string()];
+        assert!(may_share("meeting notes", &plain));
+        assert!(may_share("2024 roadmap", &[]));
+        assert!(!may_share("meeting notes", &concealed));
+        assert!(!may_share("482913", &plain));
+        assert!(!may_share("ghp_a1B2c3D4e5F6g7H8i9J0", &plain));
+    }
+
+    #[test]
+    fn test_sync_selections() {
+        use crate::config::SyncDirection;
+
+        let no_image = || false;
+        let mut sync = SyncConfig { direction: SyncDirection::PrimaryToClipboard, text_only: true, min_length: 3 };
+        assert!(should_sync(&sync, LinuxClipboardKind::Clipboard, "word", no_image));
+        assert!(!should_sync(&sync, LinuxClipboardKind::Clipboard, "ab", no_image));
+        assert!(!should_sync(&sync, LinuxClipboardKind::Clipboard, "word", || true));
+        assert!(!should_sync(&sync, LinuxClipboardKind::Primary, "word", no_image));
+
+        // The minimum length only applies to selected text
+        sync.direction = SyncDirection::Both;
+        assert!(should_sync(&sync, LinuxClipboardKind::Primary, "ab", no_image));
+
+        sync.text_only = false;
+        assert!(should_sync(&sync, LinuxClipboardKind::Clipboard, "word", || true));
+
+        sync.direction = SyncDirection::Off;
+        assert!(!should_sync(&sync, LinuxClipboardKind::Clipboard, "word", no_image));
+        assert!(!should_sync(&sync, LinuxClipboardKind::Primary, "word", no_image));
+    }
+
+    #[test]
+    fn test_take_over_plain_text_only() {
+        let types = |types: &[&str]| types.iter().map(|mime| mime.to_string()).collect::<Vec<_>>();
+        assert!(offers_only_text(&types(&["text/plain;charset=utf-8", "UTF8_STRING", "TARGETS"])));
+        assert!(!offers_only_text(&types(&["text/html", "text/plain"])));
+        assert!(!offers_only_text(&types(&["image/png"])));
+        assert!(!offers_only_text(&types(&["text/uri-list", "text/plain"])));
+        assert!(!offers_only_text(&[]));
+
+        assert!(should_take_over(copy(Owner::App(7))));
+        assert!(should_take_over(copy(Owner::Popup)));
+        assert!(!should_take_over(LastCopy { text_only: false, ..copy(Owner::App(7)) }));
+        assert!(!should_take_over(LastCopy { sensitive: true, ..copy(Owner::Unknown) }));
+        assert!(!should_take_over(copy(Owner::Daemon)));
+    }
 }
\ No newline at end of file
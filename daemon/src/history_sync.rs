@@ -0,0 +1,183 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::config::HistorySyncConfig;
+use crate::database::sync::Change;
+use crate::database::Database;
+
+/// Every machine appends its changes to `<machine id>.jsonl` in the shared
+/// directory and reads the files of the others from where it left off
+const LOG_EXTENSION: &str = "jsonl";
+
+/// Merge the history with the other machines syncing through `config.dir`
+/// every `config.interval_secs`
+pub async fn run(db: Arc<Mutex<Database>>, config: HistorySyncConfig) {
+    let Some(dir) = config.dir.as_deref().map(expand_home) else {
+        return;
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+
+    loop {
+        interval.tick().await;
+        match sync_once(&db, &dir).await {
+            Ok((0, 0)) => {}
+            Ok((sent, received)) => info!("History sync: sent {} changes, applied {}", sent, received),
+            Err(e) => warn!("History sync through {} failed: {:#}", dir.display(), e),
+        }
+    }
+}
+
+/// Apply the new changes of other machines, then append the local ones to
+/// this machine's log. The database is only locked to read and record
+/// changes, not while the logs are read and written, which may be slow on a
+/// network share. Returns how many changes were sent and applied.
+pub async fn sync_once(db: &Mutex<Database>, dir: &Path) -> Result<(usize, usize)> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    let machine = db.lock().await.sync_machine_id().await?;
+
+    let mut received = 0;
+    for file in std::fs::read_dir(dir)? {
+        let path = file?.path();
+        let Some(other) = log_machine(&path) else { continue };
+        if other == machine {
+            continue;
+        }
+
+        let offset = db.lock().await.sync_log_offset(&other).await?;
+        let (changes, offset) = read_log(&path, offset)?;
+        let db = db.lock().await;
+        if !changes.is_empty() {
+            received += db.apply_changes(changes).await?;
+        }
+        db.set_sync_log_offset(&other, offset).await?;
+    }
+
+    let pending = db.lock().await.collect_changes(&machine).await?;
+    if !pending.changes.is_empty() {
+        write_log(&dir.join(format!("{}.{}", machine, LOG_EXTENSION)), &pending.changes)?;
+    }
+    db.lock().await.mark_sent(&pending).await?;
+
+    Ok((pending.changes.len(), received))
+}
+
+fn write_log(path: &Path, changes: &[Change]) -> Result<()> {
+    let mut lines = String::new();
+    for change in changes {
+        lines.push_str(&serde_json::to_string(change)?);
+        lines.push('\n');
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines.as_bytes())?;
+    Ok(file.sync_all()?)
+}
+
+/// The machine id of a change log file
+fn log_machine(path: &Path) -> Option<String> {
+    if path.extension()? != LOG_EXTENSION {
+        return None;
+    }
+    Some(path.file_stem()?.to_str()?.to_string())
+}
+
+/// The complete lines of a log after `offset` and the offset after them. A
+/// line still being written or synced is left for next time; a log that got
+/// shorter was replaced and is read again from the start.
+fn read_log(path: &Path, offset: u64) -> Result<(Vec<Change>, u64)> {
+    let mut file = std::fs::File::open(path)?;
+    let offset = if file.metadata()?.len() < offset { 0 } else { offset };
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let complete = data.iter().rposition(|byte| *byte == b'\n').map_or(0, |end| end + 1);
+
+    let mut changes = Vec::new();
+    for line in String::from_utf8_lossy(&data[..complete]).lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(change) => changes.push(change),
+            Err(e) => warn!("Skipping invalid line in {}: {}", path.display(), e),
+        }
+    }
+    Ok((changes, offset + complete as u64))
+}
+
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn contents(db: &Mutex<Database>) -> Result<Vec<String>> {
+        let entries = db.lock().await.get_recent_entries(50).await?;
+        let mut contents: Vec<String> = entries.into_iter().map(|entry| entry.content).collect();
+        contents.sort();
+        Ok(contents)
+    }
+
+    #[tokio::test]
+    async fn test_two_machines_converge() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let shared = temp_dir.path().join("shared");
+        let laptop = Mutex::new(Database::open(&temp_dir.path().join("laptop/clipboard.db")).await?);
+        let desktop = Mutex::new(Database::open(&temp_dir.path().join("desktop/clipboard.db")).await?);
+
+        let note = laptop.lock().await.add_entry("copied on the laptop".to_string()).await?;
+        desktop.lock().await.add_entry("copied on the desktop".to_string()).await?;
+        desktop.lock().await.add_entry("copied on both".to_string()).await?;
+        laptop.lock().await.add_entry("copied on both".to_string()).await?;
+        assert_eq!(sync_once(&laptop, &shared).await?, (2, 0));
+        sync_once(&desktop, &shared).await?;
+        sync_once(&laptop, &shared).await?;
+        assert_eq!(contents(&laptop).await?.len(), 3);
+        assert_eq!(contents(&laptop).await?, contents(&desktop).await?);
+
+        // Edits, tags and removals travel too, and the latest edit wins
+        laptop.lock().await.update_entry(note, "edited on the laptop".to_string()).await?;
+        sync_once(&laptop, &shared).await?;
+        sync_once(&desktop, &shared).await?;
+        {
+            let desktop = desktop.lock().await;
+            let edited = desktop.get_recent_entries(50).await?;
+            let edited = edited.iter().find(|entry| entry.content == "edited on the laptop").unwrap().id;
+            desktop.update_entry(edited, "edited on the desktop".to_string()).await?;
+            desktop.tag_entry(edited, "work").await?;
+            desktop.clear_history().await?;
+        }
+        sync_once(&desktop, &shared).await?;
+        sync_once(&laptop, &shared).await?;
+
+        assert_eq!(contents(&laptop).await?, vec!["edited on the desktop"]);
+        assert_eq!(contents(&laptop).await?, contents(&desktop).await?);
+        assert_eq!(laptop.lock().await.entries_with_tag("work").await?[0].content, "edited on the desktop");
+
+        // Nothing changed, nothing is sent
+        assert_eq!(sync_once(&laptop, &shared).await?, (0, 0));
+        assert_eq!(sync_once(&desktop, &shared).await?, (0, 0));
+
+        // Changes not marked sent, as when the log could not be written, are
+        // collected again
+        let laptop_db = laptop.lock().await;
+        laptop_db.add_entry("copied again".to_string()).await?;
+        let machine = laptop_db.sync_machine_id().await?;
+        assert_eq!(laptop_db.collect_changes(&machine).await?.changes.len(), 1);
+        drop(laptop_db);
+        assert_eq!(sync_once(&laptop, &shared).await?, (1, 0));
+        assert_eq!(sync_once(&laptop, &shared).await?, (0, 0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,529 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use flate2::read::ZlibDecoder;
+use log::warn;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Clipboard managers whose history can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    CopyQ,
+    Cliphist,
+    Clipman,
+    GPaste,
+    Klipper,
+}
+
+impl Format {
+    pub const ALL: [Format; 5] = [Format::CopyQ, Format::Cliphist, Format::Clipman, Format::GPaste, Format::Klipper];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::CopyQ => "copyq",
+            Format::Cliphist => "cliphist",
+            Format::Clipman => "clipman",
+            Format::GPaste => "gpaste",
+            Format::Klipper => "klipper",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name.to_lowercase())
+    }
+
+    /// Where the manager keeps its history by default
+    pub fn default_path(self) -> Option<PathBuf> {
+        match self {
+            // The tab named "&clipboard", base64 encoded in the file name
+            Format::CopyQ => dirs::config_dir().map(|dir| dir.join("copyq/copyq_tab_JmNsaXBib2FyZA==.dat")),
+            Format::Cliphist => dirs::cache_dir().map(|dir| dir.join("cliphist/db")),
+            Format::Clipman => dirs::data_local_dir().map(|dir| dir.join("clipman.json")),
+            Format::GPaste => dirs::data_local_dir().map(|dir| dir.join("gpaste/history.xml")),
+            Format::Klipper => dirs::data_local_dir().map(|dir| dir.join("klipper/history2.lst")),
+        }
+    }
+}
+
+/// Read the text entries of a history file, oldest first.
+///
+/// None of the formats record when an entry was copied, so the newest entry
+/// gets the file's modification time and each older one a second less, which
+/// keeps their order and places them before anything copied since.
+pub fn read(format: Format, path: &Path) -> Result<Vec<(String, DateTime<Utc>)>> {
+    let data = std::fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let contents = match format {
+        Format::CopyQ => read_copyq(&data),
+        Format::Cliphist => read_cliphist(&data),
+        Format::Clipman => read_clipman(&data),
+        Format::GPaste => read_gpaste(&data),
+        Format::Klipper => read_klipper(&data),
+    }
+    .with_context(|| format!("{} is not a {} history file", path.display(), format.name()))?;
+
+    let contents: Vec<String> = contents.into_iter().filter(|content| !content.trim().is_empty()).collect();
+    let modified: DateTime<Utc> = std::fs::metadata(path)?.modified()?.into();
+    let count = contents.len() as i64;
+    Ok(contents
+        .into_iter()
+        .enumerate()
+        .map(|(index, content)| (content, modified - Duration::seconds(count - 1 - index as i64)))
+        .collect())
+}
+
+/// CopyQ tab files: a list of items, newest first, each a map from MIME type
+/// to data, written with Qt's QDataStream
+fn read_copyq(data: &[u8]) -> Result<Vec<String>> {
+    let mut stream = DataStream::new(data);
+
+    // Newer versions start with a header naming the format
+    let mut header = DataStream::new(data);
+    if header.string().is_ok_and(|name| name.starts_with("CopyQ")) {
+        stream = header;
+    }
+
+    let count = stream.i32()?;
+    let mut contents = Vec::new();
+    for _ in 0..count {
+        let mut text = None;
+        let marker = stream.i32()?;
+        let formats = if marker < 0 { stream.i32()? } else { marker };
+
+        for _ in 0..formats {
+            let mime = stream.string()?;
+            let (mime, bytes) = match marker {
+                // MIME types are shortened and each format may be compressed
+                -2 => {
+                    let compressed = stream.u8()? != 0;
+                    let bytes = stream.bytes()?;
+                    (copyq_mime(&mime), if compressed { q_uncompress(&bytes)? } else { bytes })
+                }
+                // Every format is compressed
+                -1 => (mime, q_uncompress(&stream.bytes()?)?),
+                _ => (mime, stream.bytes()?),
+            };
+            if mime == "text/plain" || mime.starts_with("text/plain;") {
+                text = String::from_utf8(bytes).ok();
+            }
+        }
+
+        contents.extend(text);
+    }
+
+    contents.reverse();
+    Ok(contents)
+}
+
+/// Expand a MIME type shortened by CopyQ, which replaces common prefixes with a digit
+fn copyq_mime(mime: &str) -> String {
+    let (prefix, rest) = mime.split_at(mime.chars().next().map_or(0, char::len_utf8));
+    match prefix {
+        "1" => format!("application/x-copyq-{}", rest),
+        "2" => format!("text/{}", rest),
+        "3" => format!("application/{}", rest),
+        "0" => rest.to_string(),
+        _ => mime.to_string(),
+    }
+}
+
+/// Data compressed with Qt's qCompress: the uncompressed size, then a zlib stream
+fn q_uncompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(bytes.get(4..).context("Truncated compressed data")?).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// cliphist's bbolt database, or the output of `cliphist list` (whose long
+/// entries are cut off)
+fn read_cliphist(data: &[u8]) -> Result<Vec<String>> {
+    if data.get(16..20) == Some(&BOLT_MAGIC.to_le_bytes()) {
+        let values = Bolt::new(data)?.bucket_values(b"b")?;
+        // Images and other binary copies aren't text
+        return Ok(values.into_iter().filter_map(|value| String::from_utf8(value).ok()).collect());
+    }
+
+    let text = std::str::from_utf8(data)?;
+    let mut contents: Vec<String> = text
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(id, _)| id.trim().parse::<u64>().is_ok())
+        .map(|(_, content)| content.to_string())
+        .collect();
+    if contents.is_empty() && !text.trim().is_empty() {
+        anyhow::bail!("Expected a bbolt database or `cliphist list` output");
+    }
+
+    // Listed newest first
+    contents.reverse();
+    Ok(contents)
+}
+
+/// clipman's history: a JSON array of strings, newest last
+fn read_clipman(data: &[u8]) -> Result<Vec<String>> {
+    Ok(serde_json::from_slice(data)?)
+}
+
+/// GPaste's history.xml, newest first. Only text items are read; GPaste 2
+/// wraps the text in `<value>`, older versions put it in the item directly.
+fn read_gpaste(data: &[u8]) -> Result<Vec<String>> {
+    let xml = std::str::from_utf8(data)?;
+    if !xml.contains("<history") {
+        anyhow::bail!("No <history> element");
+    }
+
+    let mut contents = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<item") {
+        let tag_end = rest[start..].find('>').context("Unterminated <item>")? + start;
+        let tag = &rest[start..tag_end];
+        let end = rest[tag_end..].find("</item>").context("Unterminated <item>")? + tag_end;
+        let body = &rest[tag_end + 1..end];
+        rest = &rest[end + "</item>".len()..];
+
+        if xml_attribute(tag, "kind").is_some_and(|kind| kind != "Text") {
+            continue;
+        }
+        let value = match (body.find("<value>"), body.rfind("</value>")) {
+            (Some(open), Some(close)) if open < close => &body[open + "<value>".len()..close],
+            _ => body,
+        };
+        contents.push(xml_text(value));
+    }
+
+    contents.reverse();
+    Ok(contents)
+}
+
+fn xml_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let end = tag[start..].find('"')? + start;
+    Some(&tag[start..end])
+}
+
+/// Character data of an element: CDATA sections as they are, the rest unescaped
+fn xml_text(value: &str) -> String {
+    let mut text = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("<![CDATA[") {
+        text.push_str(&xml_unescape(&rest[..start]));
+        let cdata = &rest[start + "<![CDATA[".len()..];
+        let end = cdata.find("]]>").unwrap_or(cdata.len());
+        text.push_str(&cdata[..end]);
+        rest = cdata.get(end + "]]>".len()..).unwrap_or_default();
+    }
+    text.push_str(&xml_unescape(rest));
+    text
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Klipper's history2.lst: a checksum, then a QDataStream with the version
+/// and the items, newest first
+fn read_klipper(data: &[u8]) -> Result<Vec<String>> {
+    let mut file = DataStream::new(data);
+    let _crc = file.u32()?;
+    let history = file.bytes()?;
+
+    let mut stream = DataStream::new(&history);
+    let _version = stream.string()?;
+    let mut contents = Vec::new();
+    while !stream.at_end() {
+        match stream.string()?.as_str() {
+            "string" => contents.push(stream.string()?),
+            "url" => {
+                let urls = (0..stream.u32()?).map(|_| stream.string()).collect::<Result<Vec<_>>>()?;
+                // Metadata is a map of string keys to string values
+                let metadata = stream.u32()?.checked_mul(2).context("Invalid Klipper URL metadata")?;
+                for _ in 0..metadata {
+                    stream.string()?;
+                }
+                let _cut = stream.u8()?;
+                contents.push(urls.join("\n"));
+            }
+            other => {
+                // Images can't be skipped without decoding them
+                warn!("Stopped reading the Klipper history at a '{}' item", other);
+                break;
+            }
+        }
+    }
+
+    contents.reverse();
+    Ok(contents)
+}
+
+/// Reader for data written with Qt's QDataStream, which is big-endian
+struct DataStream<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> DataStream<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self.position.checked_add(length).filter(|end| *end <= self.data.len());
+        let bytes = &self.data[self.position..end.context("Unexpected end of data")?];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    /// A QByteArray: its length, or 0xffffffff when null, then the bytes
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        match self.u32()? {
+            u32::MAX => Ok(Vec::new()),
+            length => Ok(self.take(length as usize)?.to_vec()),
+        }
+    }
+
+    /// A QString: like a QByteArray, holding UTF-16
+    fn string(&mut self) -> Result<String> {
+        let bytes = self.bytes()?;
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        Ok(String::from_utf16(&units)?)
+    }
+}
+
+const BOLT_MAGIC: u32 = 0xED0C_DAED;
+const BOLT_BRANCH_PAGE: u16 = 0x01;
+const BOLT_LEAF_PAGE: u16 = 0x02;
+const BOLT_BUCKET_LEAF: u32 = 0x01;
+/// Size of a page header and of the elements following it
+const BOLT_HEADER: usize = 16;
+/// Deepest branch nesting followed, far beyond what a real file reaches, so
+/// pages pointing back at their parents can't recurse forever
+const BOLT_MAX_DEPTH: usize = 32;
+
+/// Just enough of a bbolt (BoltDB) reader to list the values of a top-level bucket
+struct Bolt<'a> {
+    data: &'a [u8],
+    page_size: usize,
+    root: u64,
+}
+
+impl<'a> Bolt<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        let field = |offset: usize, size: usize| -> Result<u64> {
+            let bytes = data.get(offset..offset + size).context("Truncated bbolt file")?;
+            Ok(bytes.iter().rev().fold(0, |value, byte| value << 8 | *byte as u64))
+        };
+        let page_size = field(24, 4)? as usize;
+        if page_size < 512 {
+            anyhow::bail!("Invalid bbolt page size");
+        }
+
+        // Two meta pages; the one with the newer transaction is current
+        let mut current = None;
+        for offset in [0, page_size] {
+            if field(offset + 16, 4)? != BOLT_MAGIC as u64 {
+                continue;
+            }
+            let (root, txid) = (field(offset + 32, 8)?, field(offset + 64, 8)?);
+            if current.is_none_or(|(_, newest)| txid > newest) {
+                current = Some((root, txid));
+            }
+        }
+        let (root, _) = current.context("No valid bbolt meta page")?;
+
+        Ok(Self { data, page_size, root })
+    }
+
+    /// Values of a bucket in key order, skipping nested buckets
+    fn bucket_values(&self, name: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let mut bucket = None;
+        self.walk(self.page(self.root)?, 0, &mut |key, value, flags| {
+            if key == name && flags & BOLT_BUCKET_LEAF != 0 {
+                bucket = Some(value);
+            }
+        })?;
+        let bucket = bucket.with_context(|| format!("No '{}' bucket", String::from_utf8_lossy(name)))?;
+
+        // A small bucket is stored inline, right after its header
+        let root = u64::from_le_bytes(bucket.get(..8).context("Truncated bucket")?.try_into()?);
+        let page = if root == 0 { bucket.get(16..).context("Truncated bucket")? } else { self.page(root)? };
+
+        let mut values = Vec::new();
+        self.walk(page, 0, &mut |_, value, flags| {
+            if flags & BOLT_BUCKET_LEAF == 0 {
+                values.push(value.to_vec());
+            }
+        })?;
+        Ok(values)
+    }
+
+    fn page(&self, id: u64) -> Result<&'a [u8]> {
+        let start = (id as usize).checked_mul(self.page_size).filter(|start| *start < self.data.len());
+        Ok(&self.data[start.context("Page outside the bbolt file")?..])
+    }
+
+    /// Visit the key, value and flags of every leaf element under a page,
+    /// `depth` branch pages below where the walk started
+    fn walk(&self, page: &'a [u8], depth: usize, visit: &mut dyn FnMut(&'a [u8], &'a [u8], u32)) -> Result<()> {
+        if depth > BOLT_MAX_DEPTH {
+            anyhow::bail!("bbolt pages nested too deeply");
+        }
+        let u16_at = |offset: usize| -> Result<u16> {
+            Ok(u16::from_le_bytes(page.get(offset..offset + 2).context("Truncated page")?.try_into()?))
+        };
+        let u32_at = |offset: usize| -> Result<u32> {
+            Ok(u32::from_le_bytes(page.get(offset..offset + 4).context("Truncated page")?.try_into()?))
+        };
+        let slice = |offset: usize, length: u32| -> Result<&'a [u8]> {
+            page.get(offset..offset + length as usize).context("Truncated page")
+        };
+
+        let (flags, count) = (u16_at(8)?, u16_at(10)? as usize);
+        for index in 0..count {
+            let element = BOLT_HEADER + index * BOLT_HEADER;
+            if flags & BOLT_LEAF_PAGE != 0 {
+                let (element_flags, position) = (u32_at(element)?, u32_at(element + 4)? as usize);
+                let (key_size, value_size) = (u32_at(element + 8)?, u32_at(element + 12)?);
+                let key = slice(element + position, key_size)?;
+                let value = slice(element + position + key_size as usize, value_size)?;
+                visit(key, value, element_flags);
+            } else if flags & BOLT_BRANCH_PAGE != 0 {
+                let child = page.get(element + 8..element + 16).context("Truncated page")?;
+                self.walk(self.page(u64::from_le_bytes(child.try_into()?))?, depth + 1, visit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// QDataStream encoding of a QString
+    fn q_string(text: &str) -> Vec<u8> {
+        let units: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        [(units.len() as u32).to_be_bytes().to_vec(), units].concat()
+    }
+
+    fn q_bytes(bytes: &[u8]) -> Vec<u8> {
+        [(bytes.len() as u32).to_be_bytes().to_vec(), bytes.to_vec()].concat()
+    }
+
+    #[test]
+    fn test_read_qt_formats() {
+        // CopyQ: two items, newest first, the first with a shortened MIME type
+        let mut copyq = 2i32.to_be_bytes().to_vec();
+        for (mime, text) in [("2plain", "newer"), ("0text/plain", "older")] {
+            copyq.extend((-2i32).to_be_bytes());
+            copyq.extend(2i32.to_be_bytes());
+            copyq.extend([q_string("1owner"), vec![0], q_bytes(b"x")].concat());
+            copyq.extend([q_string(mime), vec![0], q_bytes(text.as_bytes())].concat());
+        }
+        assert_eq!(read_copyq(&copyq).unwrap(), vec!["older", "newer"]);
+
+        // Klipper: the items follow a version string inside a checksummed byte array
+        let history = [
+            q_string("0.9.7"),
+            q_string("string"),
+            q_string("héllo"),
+            q_string("url"),
+            1u32.to_be_bytes().to_vec(),
+            q_string("file:///tmp/a"),
+            0u32.to_be_bytes().to_vec(),
+            vec![0],
+            q_string("string"),
+            q_string("first"),
+        ]
+        .concat();
+        let klipper = [0u32.to_be_bytes().to_vec(), q_bytes(&history)].concat();
+        assert_eq!(read_klipper(&klipper).unwrap(), vec!["first", "file:///tmp/a", "héllo"]);
+
+        // A URL item claiming more metadata than fits in a count is an error, not a crash
+        let history = [q_string("0.9.7"), q_string("url"), 0u32.to_be_bytes().to_vec(), u32::MAX.to_be_bytes().to_vec()]
+            .concat();
+        let klipper = [0u32.to_be_bytes().to_vec(), q_bytes(&history)].concat();
+        assert!(read_klipper(&klipper).is_err());
+    }
+
+    #[test]
+    fn test_read_text_formats() {
+        let gpaste = r#"<?xml version="1.0" encoding="UTF-8"?>
+<history version="2.0">
+  <item kind="Text" uuid="1"><value><![CDATA[if a < b]]></value></item>
+  <item kind="Image" uuid="2"><value><![CDATA[image]]></value></item>
+  <item kind="Text" uuid="3"><value>fish &amp; chips</value></item>
+</history>"#;
+        assert_eq!(read_gpaste(gpaste.as_bytes()).unwrap(), vec!["fish & chips", "if a < b"]);
+
+        assert_eq!(read_clipman(br#"["old", "new"]"#).unwrap(), vec!["old", "new"]);
+        assert_eq!(read_cliphist(b"12\tnew\n3\told\ttabbed\n").unwrap(), vec!["old\ttabbed", "new"]);
+        assert!(read_cliphist(b"not a list").is_err());
+    }
+
+    #[test]
+    fn test_read_bolt_bucket() {
+        const PAGE: usize = 4096;
+        let mut data = vec![0u8; PAGE * 4];
+
+        // Meta pages; the second one has the newer transaction and points at page 3
+        for (page, root, txid) in [(0, 2u64, 1u64), (1, 3, 2)] {
+            let meta = page * PAGE;
+            data[meta + 8..meta + 10].copy_from_slice(&4u16.to_le_bytes());
+            data[meta + 16..meta + 20].copy_from_slice(&BOLT_MAGIC.to_le_bytes());
+            data[meta + 24..meta + 28].copy_from_slice(&(PAGE as u32).to_le_bytes());
+            data[meta + 32..meta + 40].copy_from_slice(&root.to_le_bytes());
+            data[meta + 64..meta + 72].copy_from_slice(&txid.to_le_bytes());
+        }
+
+        // An inline bucket page holding two entries keyed by id
+        let leaf = |entries: &[(&[u8], &[u8], u32)]| -> Vec<u8> {
+            let mut page = vec![0u8; BOLT_HEADER];
+            page[8..10].copy_from_slice(&BOLT_LEAF_PAGE.to_le_bytes());
+            page[10..12].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+            let mut payload = Vec::new();
+            for (index, (key, value, flags)) in entries.iter().enumerate() {
+                let position = (entries.len() - index) * BOLT_HEADER + payload.len();
+                page.extend(flags.to_le_bytes());
+                page.extend((position as u32).to_le_bytes());
+                page.extend((key.len() as u32).to_le_bytes());
+                page.extend((value.len() as u32).to_le_bytes());
+                payload.extend(*key);
+                payload.extend(*value);
+            }
+            [page, payload].concat()
+        };
+        let entries = leaf(&[(&1u64.to_be_bytes(), b"first", 0), (&2u64.to_be_bytes(), b"second", 0)]);
+        let bucket = [vec![0u8; 16], entries].concat();
+        let root = leaf(&[(b"b", &bucket, BOLT_BUCKET_LEAF)]);
+        data[3 * PAGE..3 * PAGE + root.len()].copy_from_slice(&root);
+
+        assert_eq!(read_cliphist(&data).unwrap(), vec!["first", "second"]);
+
+        // A branch page listing itself as its child
+        let branch = 3 * PAGE;
+        data[branch..branch + 2 * BOLT_HEADER].fill(0);
+        data[branch + 8..branch + 10].copy_from_slice(&BOLT_BRANCH_PAGE.to_le_bytes());
+        data[branch + 10..branch + 12].copy_from_slice(&1u16.to_le_bytes());
+        data[branch + BOLT_HEADER + 8..branch + BOLT_HEADER + 16].copy_from_slice(&3u64.to_le_bytes());
+        assert!(read_cliphist(&data).is_err());
+    }
+}
@@ -1,4 +1,31 @@
+pub mod backup;
 pub mod clipboard_watcher;
+pub mod history_sync;
+pub mod import;
+pub mod mdns;
+pub mod share;
+pub mod source;
+
+#[path = "../../src/actions.rs"]
+pub mod actions;
+
+#[path = "../../src/classify.rs"]
+pub mod classify;
+
+#[path = "../../src/config.rs"]
+pub mod config;
 
 #[path = "../../src/database/mod.rs"]
-pub mod database;
\ No newline at end of file
+pub mod database;
+
+#[path = "../../src/hooks.rs"]
+pub mod hooks;
+
+#[path = "../../src/plugins.rs"]
+pub mod plugins;
+
+#[path = "../../src/transform.rs"]
+pub mod transform;
+
+#[path = "../../src/template.rs"]
+pub mod template;
\ No newline at end of file
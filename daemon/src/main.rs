@@ -3,21 +3,64 @@ use log::info;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+// Quick actions and transforms are only offered by the UI, but plugins register them
+#[path = "../../src/actions.rs"]
+#[allow(dead_code)]
+mod actions;
+
+mod backup;
+mod cli;
 mod clipboard_watcher;
 use clipboard_watcher::ClipboardWatcher;
+mod history_sync;
+mod import;
+mod mdns;
+mod share;
+
+// The display helpers in here are only used by the UI
+#[path = "../../src/classify.rs"]
+#[allow(dead_code)]
+mod classify;
+
+#[path = "../../src/config.rs"]
+mod config;
+use config::Config;
 
 #[path = "../../src/database/mod.rs"]
 mod database;
 use database::Database;
 
+// Select hooks are run by the UI
+#[path = "../../src/hooks.rs"]
+#[allow(dead_code)]
+mod hooks;
+
+#[path = "../../src/plugins.rs"]
+#[allow(dead_code)]
+mod plugins;
+
+#[path = "../../src/template.rs"]
+mod template;
+
+#[path = "../../src/transform.rs"]
+#[allow(dead_code)]
+mod transform;
+
 #[path = "../../src/shortcut_manager.rs"]
 mod shortcut_manager;
 use shortcut_manager::ShortcutManager;
 
+mod source;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
     
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        return cli::run(command, &args[1..]).await;
+    }
+    
     info!("Starting Clippoo daemon");
     
     // Check and register shortcut if needed (only on GNOME for now)
@@ -32,12 +75,40 @@ async fn main() -> Result<()> {
         }
     }
     
+    let config = Config::load_or_default();
+    
     // Initialize database
-    let db = Arc::new(Mutex::new(Database::new().await?));
+    let mut db = Database::new().await?.with_blob_threshold(config.storage.blob_threshold);
+    db.set_dedup_policy(config.dedup.clone()).await?;
+    let db = Arc::new(Mutex::new(db));
     info!("Database initialized");
     
+    if config.backup.enabled {
+        tokio::spawn(backup::run_scheduled(db.clone(), config.backup.clone()));
+    }
+    if config.history_sync.dir.is_some() {
+        tokio::spawn(history_sync::run(db.clone(), config.history_sync.clone()));
+    }
+    
     // Create clipboard watcher
-    let watcher = ClipboardWatcher::new(db.clone());
+    let share = if config.share.enabled {
+        match share::Share::start(db.clone(), &config.share, config.storage.max_size).await {
+            Ok(share) => {
+                info!("Listening for share peers on {}", share.local_addr());
+                Some(share)
+            }
+            Err(e) => {
+                log::warn!("Not sharing entries: {:#}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut watcher = ClipboardWatcher::new(db.clone(), config);
+    if let Some(share) = share {
+        watcher = watcher.with_share(share);
+    }
     
     // Start monitoring clipboard
     info!("Starting clipboard monitoring");
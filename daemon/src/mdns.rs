@@ -0,0 +1,254 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use tokio::net::UdpSocket;
+use tokio::time::{interval, Duration};
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+/// DNS-SD service type daemons announce themselves under
+const SERVICE: &str = "_clippoo._tcp.local";
+
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Seconds other hosts may remember our records
+const TTL: u32 = 120;
+
+/// Answer queries for the service with this daemon's `name` and `port`, and
+/// call `found` with the name and address of every other daemon that
+/// announces itself. Runs until the socket fails.
+pub async fn run<F>(name: String, port: u16, found: F) -> Result<()>
+where
+    F: Fn(String, SocketAddr),
+{
+    let socket = bind().context("Could not listen for mDNS")?;
+    let group = SocketAddr::V4(SocketAddrV4::new(GROUP, PORT));
+    let response = response(&name, port);
+    let mut queries = interval(Duration::from_secs(60));
+    let mut buf = vec![0u8; 9000];
+
+    info!("Announcing {} as {}.{}", name, name, SERVICE);
+    socket.send_to(&response, group).await?;
+
+    loop {
+        tokio::select! {
+            _ = queries.tick() => {
+                socket.send_to(&query(), group).await?;
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = received?;
+                let Some(packet) = Packet::parse(&buf[..len]) else {
+                    continue;
+                };
+
+                if packet.is_query {
+                    if packet.asks_for_service() {
+                        debug!("Answering mDNS query from {}", from);
+                        socket.send_to(&response, group).await?;
+                    }
+                    continue;
+                }
+                for (peer, peer_port) in packet.instances() {
+                    if peer != label(&name) {
+                        found(peer, SocketAddr::new(from.ip(), peer_port));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A UDP socket joined to the mDNS group, sharing the port with other
+/// responders such as Avahi
+fn bind() -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT)).into())?;
+    socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    // Daemons on the same machine find each other too
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+fn query() -> Vec<u8> {
+    let mut packet = header(0, 1, 0);
+    write_name(&mut packet, SERVICE);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+/// PTR, SRV and TXT records for `name`. Receivers take the address from the
+/// packet, so no A record is needed.
+fn response(name: &str, port: u16) -> Vec<u8> {
+    let instance = format!("{}.{}", label(name), SERVICE);
+    let mut packet = header(0x8400, 0, 3);
+
+    let mut ptr = Vec::new();
+    write_name(&mut ptr, &instance);
+    write_record(&mut packet, SERVICE, TYPE_PTR, &ptr);
+
+    let mut srv = vec![0, 0, 0, 0];
+    srv.extend_from_slice(&port.to_be_bytes());
+    write_name(&mut srv, &format!("{}.local", label(name)));
+    write_record(&mut packet, &instance, TYPE_SRV, &srv);
+
+    write_record(&mut packet, &instance, TYPE_TXT, &[0]);
+    packet
+}
+
+/// A host name made fit for a single DNS label
+fn label(name: &str) -> String {
+    let label: String = name.chars().map(|c| if c == '.' { '-' } else { c }).collect();
+    let mut end = label.len().min(63);
+    while !label.is_char_boundary(end) {
+        end -= 1;
+    }
+    label[..end].to_string()
+}
+
+fn header(flags: u16, questions: u16, answers: u16) -> Vec<u8> {
+    let mut packet = vec![0, 0];
+    for field in [flags, questions, answers, 0, 0] {
+        packet.extend_from_slice(&field.to_be_bytes());
+    }
+    packet
+}
+
+fn write_name(packet: &mut Vec<u8>, name: &str) {
+    for part in name.split('.') {
+        packet.push(part.len() as u8);
+        packet.extend_from_slice(part.as_bytes());
+    }
+    packet.push(0);
+}
+
+fn write_record(packet: &mut Vec<u8>, name: &str, kind: u16, data: &[u8]) {
+    write_name(packet, name);
+    packet.extend_from_slice(&kind.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet.extend_from_slice(&TTL.to_be_bytes());
+    packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    packet.extend_from_slice(data);
+}
+
+/// The parts of an mDNS packet the service discovery looks at
+struct Packet {
+    is_query: bool,
+    questions: Vec<(String, u16)>,
+    /// Name, type and data offset of every record
+    records: Vec<(String, u16, usize)>,
+    data: Vec<u8>,
+}
+
+impl Packet {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let field = |at: usize| Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?));
+        let is_query = field(2)? & 0x8000 == 0;
+        let (questions, records) = (field(4)?, field(6)? as usize + field(8)? as usize + field(10)? as usize);
+
+        let mut offset = 12;
+        let mut packet = Packet { is_query, questions: Vec::new(), records: Vec::new(), data: data.to_vec() };
+        for _ in 0..questions {
+            let (name, end) = read_name(data, offset)?;
+            packet.questions.push((name, field(end)?));
+            offset = end + 4;
+        }
+        for _ in 0..records {
+            let (name, end) = read_name(data, offset)?;
+            let length = field(end + 8)? as usize;
+            packet.records.push((name, field(end)?, end + 10));
+            offset = end + 10 + length;
+            if offset > data.len() {
+                return None;
+            }
+        }
+        Some(packet)
+    }
+
+    fn asks_for_service(&self) -> bool {
+        self.questions
+            .iter()
+            .any(|(name, kind)| name.eq_ignore_ascii_case(SERVICE) && matches!(*kind, TYPE_PTR | TYPE_ANY))
+    }
+
+    /// Instance labels of the service announced in the packet, with their port
+    fn instances(&self) -> Vec<(String, u16)> {
+        let suffix = format!(".{}", SERVICE);
+        let mut instances = Vec::new();
+        for (name, kind, at) in &self.records {
+            if *kind != TYPE_SRV || !name.to_ascii_lowercase().ends_with(&suffix) {
+                continue;
+            }
+            let Some(port) = self.data.get(at + 4..at + 6) else { continue };
+            let label = name[..name.len() - suffix.len()].to_string();
+            instances.push((label, u16::from_be_bytes([port[0], port[1]])));
+        }
+        instances
+    }
+}
+
+/// The name at `offset` and the offset after it, following compression
+/// pointers
+fn read_name(data: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut parts = Vec::new();
+    let mut end = None;
+    for _ in 0..128 {
+        let length = *data.get(offset)? as usize;
+        if length & 0xc0 == 0xc0 {
+            let pointer = (length & 0x3f) << 8 | *data.get(offset + 1)? as usize;
+            end.get_or_insert(offset + 2);
+            offset = pointer;
+        } else if length == 0 {
+            return Some((parts.join("."), end.unwrap_or(offset + 1)));
+        } else {
+            parts.push(String::from_utf8_lossy(data.get(offset + 1..offset + 1 + length)?).into_owned());
+            offset += 1 + length;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announcements_are_understood() {
+        let query = Packet::parse(&query()).unwrap();
+        assert!(query.is_query);
+        assert!(query.asks_for_service());
+
+        let response = Packet::parse(&response("desk.example", 7315)).unwrap();
+        assert!(!response.is_query);
+        assert_eq!(response.instances(), vec![("desk-example".to_string(), 7315)]);
+
+        // Names may point back at earlier ones, here the question at offset 12
+        let mut packet = header(0x8400, 1, 1);
+        write_name(&mut packet, SERVICE);
+        packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        let mut srv = vec![0, 0, 0, 0, 0x1c, 0xa3];
+        write_name(&mut srv, "laptop.local");
+        packet.extend_from_slice(&[6, b'l', b'a', b'p', b't', b'o', b'p', 0xc0, 12]);
+        packet.extend_from_slice(&TYPE_SRV.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet.extend_from_slice(&TTL.to_be_bytes());
+        packet.extend_from_slice(&(srv.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&srv);
+        assert_eq!(Packet::parse(&packet).unwrap().instances(), vec![("laptop".to_string(), 7331)]);
+
+        // Truncated packets are ignored
+        assert!(Packet::parse(&packet[..packet.len() - 3]).is_none());
+
+        // Record counts adding up past u16 don't overflow
+        let mut packet = header(0x8400, 0, 0xffff);
+        packet[8..12].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert!(Packet::parse(&packet).is_none());
+    }
+}
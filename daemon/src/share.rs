@@ -0,0 +1,434 @@
+use anyhow::{Context, Result};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::{timeout, Duration};
+
+use crate::config::ShareConfig;
+use crate::database::{ClipboardEntry, Database, EntryRecord};
+use crate::mdns;
+
+/// Bytes of the random nonce each side sends when connecting
+const NONCE: usize = 32;
+const TAG: usize = 16;
+/// Time a peer gets to connect and authenticate
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Live sharing of new copies with the other daemons on the network that
+/// know the same key.
+///
+/// Every copy is sent to every known peer over its own connection. Both
+/// sides prove they know the key before anything else is sent, and entries
+/// travel encrypted with keys derived from it for that connection alone.
+#[derive(Clone)]
+pub struct Share {
+    sender: mpsc::UnboundedSender<EntryRecord>,
+    peers: Arc<Mutex<HashMap<String, SocketAddr>>>,
+    local_addr: SocketAddr,
+}
+
+impl Share {
+    /// Listen for peers, find them with mDNS when `config.discover` is set and
+    /// start sending what is published. Received entries are stored in `db`
+    /// along with the name of the machine they came from.
+    pub async fn start(db: Arc<Mutex<Database>>, config: &ShareConfig, max_size: usize) -> Result<Self> {
+        if config.key.trim().len() < 16 {
+            anyhow::bail!("share.key must be at least 16 characters, see `clippoo-daemon share-key`");
+        }
+        let name = config.name.clone().unwrap_or_else(|| gethostname::gethostname().to_string_lossy().into_owned());
+        let key = Arc::new(config.key.trim().as_bytes().to_vec());
+
+        let listener = TcpListener::bind(("0.0.0.0", config.port))
+            .await
+            .with_context(|| format!("Could not listen on port {}", config.port))?;
+        let local_addr = listener.local_addr()?;
+        info!("Sharing entries as {}", name);
+
+        let mut peers = HashMap::new();
+        for peer in &config.peers {
+            match tokio::net::lookup_host(peer).await.ok().and_then(|mut addrs| addrs.next()) {
+                Some(addr) => {
+                    peers.insert(peer.clone(), addr);
+                }
+                None => warn!("Could not resolve share peer {}", peer),
+            }
+        }
+        let peers = Arc::new(Mutex::new(peers));
+
+        // Entries can be as large as the largest stored copy, plus their metadata
+        let max_frame = max_size + 64 * 1024;
+        tokio::spawn(listen(listener, db, name.clone(), key.clone(), max_frame));
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(send(receiver, peers.clone(), name.clone(), key));
+        let share = Self { sender, peers, local_addr };
+
+        if config.discover {
+            let found = share.clone();
+            tokio::spawn(async move {
+                let result = mdns::run(name, local_addr.port(), move |peer, addr| {
+                    let found = found.clone();
+                    tokio::spawn(async move { found.add_peer(peer, addr).await });
+                })
+                .await;
+                if let Err(e) = result {
+                    warn!("Peer discovery stopped: {:#}", e);
+                }
+            });
+        }
+
+        Ok(share)
+    }
+
+    /// Send a newly stored entry to the peers
+    pub fn publish(&self, entry: &ClipboardEntry) {
+        let record = EntryRecord {
+            id: 0,
+            content: entry.content.clone(),
+            timestamp: entry.timestamp,
+            use_count: 1,
+            last_used: Some(entry.timestamp),
+            source_app: entry.source_app.clone(),
+            window_title: entry.window_title.clone(),
+            source_url: entry.source_url.clone(),
+            content_type: entry.content_type.clone(),
+            language: entry.language.clone(),
+            selection: entry.selection,
+            tags: Vec::new(),
+            blob: None,
+            expires_at: entry.expires_at,
+            origin_host: None,
+        };
+        let _ = self.sender.send(record);
+    }
+
+    /// Send new entries to `addr` too, replacing the address known for `name`
+    pub async fn add_peer(&self, name: String, addr: SocketAddr) {
+        if self.peers.lock().await.insert(name.clone(), addr) != Some(addr) {
+            info!("Sharing with {} at {}", name, addr);
+        }
+    }
+
+    /// Address the daemon listens for peers on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+/// A random key for `share.key`, to copy to every machine
+pub fn generate_key() -> Result<String> {
+    let mut key = [0u8; 24];
+    openssl::rand::rand_bytes(&mut key)?;
+    Ok(key.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+async fn listen(listener: TcpListener, db: Arc<Mutex<Database>>, name: String, key: Arc<Vec<u8>>, max_frame: usize) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept a share peer: {}", e);
+                continue;
+            }
+        };
+
+        let (db, name, key) = (db.clone(), name.clone(), key.clone());
+        tokio::spawn(async move {
+            if let Err(e) = receive(stream, &db, &name, &key, max_frame).await {
+                warn!("Share connection from {} failed: {:#}", addr, e);
+            }
+        });
+    }
+}
+
+async fn receive(mut stream: TcpStream, db: &Mutex<Database>, name: &str, key: &[u8], max_frame: usize) -> Result<()> {
+    let (peer, mut session) = timeout(HANDSHAKE_TIMEOUT, Session::accept(&mut stream, name, key))
+        .await
+        .context("Handshake timed out")??;
+
+    while let Some(data) = session.read(&mut stream, max_frame).await? {
+        let mut record: EntryRecord = serde_json::from_slice(&data)?;
+        record.tags.clear();
+        record.origin_host = Some(peer.clone());
+        db.lock().await.receive_entries(vec![record]).await?;
+        info!("Received an entry from {}", peer);
+    }
+    Ok(())
+}
+
+async fn send(
+    mut receiver: mpsc::UnboundedReceiver<EntryRecord>,
+    peers: Arc<Mutex<HashMap<String, SocketAddr>>>,
+    name: String,
+    key: Arc<Vec<u8>>,
+) {
+    while let Some(record) = receiver.recv().await {
+        let data = match serde_json::to_vec(&record) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Could not encode an entry to share: {}", e);
+                continue;
+            }
+        };
+
+        let peers: Vec<(String, SocketAddr)> = peers.lock().await.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        for (peer, addr) in peers {
+            match send_to(addr, &name, &key, &data).await {
+                Ok(()) => debug!("Shared an entry with {}", peer),
+                Err(e) => warn!("Could not share an entry with {} at {}: {:#}", peer, addr, e),
+            }
+        }
+    }
+}
+
+async fn send_to(addr: SocketAddr, name: &str, key: &[u8], data: &[u8]) -> Result<()> {
+    let mut stream = timeout(HANDSHAKE_TIMEOUT, TcpStream::connect(addr)).await.context("Connecting timed out")??;
+    let (_, mut session) = timeout(HANDSHAKE_TIMEOUT, Session::connect(&mut stream, name, key))
+        .await
+        .context("Handshake timed out")??;
+    session.write(&mut stream, data).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Keys of an authenticated connection, one per direction
+struct Session {
+    send_key: [u8; 32],
+    receive_key: [u8; 32],
+    sent: u64,
+    received: u64,
+}
+
+impl Session {
+    async fn connect(stream: &mut TcpStream, name: &str, key: &[u8]) -> Result<(String, Self)> {
+        let hello = hello(name)?;
+        write_frame(stream, &hello).await?;
+        let reply = read_frame(stream, 1024).await?.context("The peer closed the connection")?;
+
+        let keys = Keys::derive(key, &hello, &reply)?;
+        write_frame(stream, &keys.proof(b"client", &hello, &reply)).await?;
+        let proof = read_frame(stream, 64).await?.context("The peer doesn't know the key")?;
+        keys.verify(b"server", &hello, &reply, &proof)?;
+
+        let session = Self { send_key: keys.client, receive_key: keys.server, sent: 0, received: 0 };
+        Ok((peer_name(&reply)?, session))
+    }
+
+    async fn accept(stream: &mut TcpStream, name: &str, key: &[u8]) -> Result<(String, Self)> {
+        let hello_received = read_frame(stream, 1024).await?.context("The peer closed the connection")?;
+        let reply = hello(name)?;
+        write_frame(stream, &reply).await?;
+
+        let keys = Keys::derive(key, &hello_received, &reply)?;
+        let proof = read_frame(stream, 64).await?.context("The peer closed the connection")?;
+        keys.verify(b"client", &hello_received, &reply, &proof)?;
+        write_frame(stream, &keys.proof(b"server", &hello_received, &reply)).await?;
+
+        let session = Self { send_key: keys.server, receive_key: keys.client, sent: 0, received: 0 };
+        Ok((peer_name(&hello_received)?, session))
+    }
+
+    async fn write(&mut self, stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+        let mut tag = [0u8; TAG];
+        let mut frame =
+            encrypt_aead(Cipher::chacha20_poly1305(), &self.send_key, Some(&nonce(self.sent)), &[], data, &mut tag)?;
+        frame.extend_from_slice(&tag);
+        self.sent += 1;
+        write_frame(stream, &frame).await
+    }
+
+    async fn read(&mut self, stream: &mut TcpStream, max_size: usize) -> Result<Option<Vec<u8>>> {
+        let Some(frame) = read_frame(stream, max_size + TAG).await? else {
+            return Ok(None);
+        };
+        if frame.len() < TAG {
+            anyhow::bail!("Frame too short");
+        }
+        let (data, tag) = frame.split_at(frame.len() - TAG);
+        let data = decrypt_aead(Cipher::chacha20_poly1305(), &self.receive_key, Some(&nonce(self.received)), &[], data, tag)
+            .map_err(|_| anyhow::anyhow!("Could not decrypt a frame"))?;
+        self.received += 1;
+        Ok(Some(data))
+    }
+}
+
+/// Keys derived from the shared key and both hellos, so every connection
+/// gets its own
+struct Keys {
+    client: [u8; 32],
+    server: [u8; 32],
+    auth: [u8; 32],
+}
+
+impl Keys {
+    fn derive(key: &[u8], client_hello: &[u8], server_hello: &[u8]) -> Result<Self> {
+        if client_hello.len() < NONCE || server_hello.len() < NONCE {
+            anyhow::bail!("Invalid hello from the peer");
+        }
+        let salt = [&client_hello[..NONCE], &server_hello[..NONCE]].concat();
+        let hkdf = Hkdf::<Sha256>::new(Some(&salt), key);
+        let mut keys = Self { client: [0; 32], server: [0; 32], auth: [0; 32] };
+        for (info, out) in [
+            (&b"clippoo share client"[..], &mut keys.client),
+            (b"clippoo share server", &mut keys.server),
+            (b"clippoo share auth", &mut keys.auth),
+        ] {
+            hkdf.expand(info, out).map_err(|_| anyhow::anyhow!("Could not derive keys"))?;
+        }
+        Ok(keys)
+    }
+
+    fn mac(&self, role: &[u8], client_hello: &[u8], server_hello: &[u8]) -> Result<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.auth)?;
+        mac.update(role);
+        mac.update(client_hello);
+        mac.update(server_hello);
+        Ok(mac)
+    }
+
+    fn proof(&self, role: &[u8], client_hello: &[u8], server_hello: &[u8]) -> Vec<u8> {
+        self.mac(role, client_hello, server_hello)
+            .map(|mac| mac.finalize().into_bytes().to_vec())
+            .unwrap_or_default()
+    }
+
+    fn verify(&self, role: &[u8], client_hello: &[u8], server_hello: &[u8], proof: &[u8]) -> Result<()> {
+        self.mac(role, client_hello, server_hello)?
+            .verify_slice(proof)
+            .map_err(|_| anyhow::anyhow!("The peer doesn't know the key"))
+    }
+}
+
+/// A random nonce followed by the name of the machine
+fn hello(name: &str) -> Result<Vec<u8>> {
+    let mut hello = vec![0u8; NONCE];
+    openssl::rand::rand_bytes(&mut hello)?;
+    hello.extend_from_slice(name.as_bytes());
+    Ok(hello)
+}
+
+fn peer_name(hello: &[u8]) -> Result<String> {
+    let name = std::str::from_utf8(hello.get(NONCE..).unwrap_or_default())?.trim();
+    if name.is_empty() {
+        anyhow::bail!("The peer sent no name");
+    }
+    Ok(name.to_string())
+}
+
+fn nonce(counter: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+async fn write_frame(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+    stream.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    stream.write_all(data).await?;
+    Ok(())
+}
+
+/// The next length-prefixed frame, or None when the peer closed the connection
+async fn read_frame(stream: &mut TcpStream, max_size: usize) -> Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 4];
+    match stream.read_exact(&mut length).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let length = u32::from_be_bytes(length) as usize;
+    if length > max_size {
+        anyhow::bail!("Frame of {} bytes is over the limit", length);
+    }
+    let mut data = vec![0u8; length];
+    stream.read_exact(&mut data).await?;
+    Ok(Some(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Selection;
+
+    async fn start(dir: &std::path::Path, name: &str, key: &str) -> Result<(Arc<Mutex<Database>>, Share)> {
+        let db = Arc::new(Mutex::new(Database::open(&dir.join(name).join("clipboard.db")).await?));
+        let config = ShareConfig {
+            enabled: true,
+            name: Some(name.to_string()),
+            key: key.to_string(),
+            port: 0,
+            peers: Vec::new(),
+            discover: false,
+        };
+        let share = Share::start(db.clone(), &config, 1024 * 1024).await?;
+        Ok((db, share))
+    }
+
+    async fn wait_for_entries(db: &Mutex<Database>, count: usize) -> Result<Vec<ClipboardEntry>> {
+        for _ in 0..100 {
+            let entries = db.lock().await.get_recent_entries(10).await?;
+            if entries.len() >= count {
+                return Ok(entries);
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        db.lock().await.get_recent_entries(10).await
+    }
+
+    #[tokio::test]
+    async fn test_entries_reach_peers_with_the_key() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let key = generate_key()?;
+        let (_, laptop) = start(temp_dir.path(), "laptop", &key).await?;
+        let (desktop_db, desktop) = start(temp_dir.path(), "desktop", &key).await?;
+        let (stranger_db, stranger) = start(temp_dir.path(), "stranger", "some other shared key").await?;
+        let loopback = |share: &Share| SocketAddr::from(([127, 0, 0, 1], share.local_addr().port()));
+        laptop.add_peer("desktop".into(), loopback(&desktop)).await;
+        laptop.add_peer("stranger".into(), loopback(&stranger)).await;
+
+        let entry = ClipboardEntry {
+            id: 1,
+            content: "shared text".to_string(),
+            timestamp: chrono::Utc::now(),
+            is_default: true,
+            source_app: Some("firefox".to_string()),
+            window_title: None,
+            source_url: None,
+            content_type: None,
+            language: None,
+            selection: Selection::Clipboard,
+            blob: None,
+            expires_at: None,
+            origin_host: None,
+        };
+        laptop.publish(&entry);
+
+        let received = wait_for_entries(&desktop_db, 1).await?;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].content, "shared text");
+        assert_eq!(received[0].source_app.as_deref(), Some("firefox"));
+        assert!(!received[0].is_default);
+        assert_eq!(received[0].origin_host.as_deref(), Some("laptop"));
+        assert!(desktop_db.lock().await.get_entry_tags().await?.is_empty());
+
+        // Any name works as the origin, even one that is no valid tag
+        let record = br#"{"content":"from an odd name","timestamp":"2024-05-01T09:00:00Z"}"#;
+        send_to(loopback(&desktop), "#", key.as_bytes(), record).await?;
+        let received = wait_for_entries(&desktop_db, 2).await?;
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].origin_host.as_deref(), Some("#"));
+
+        // A peer with another key gets nothing
+        assert!(send_to(loopback(&stranger), "laptop", key.as_bytes(), b"{}").await.is_err());
+        assert!(stranger_db.lock().await.get_recent_entries(10).await?.is_empty());
+        Ok(())
+    }
+}
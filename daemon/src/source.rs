@@ -0,0 +1,231 @@
+use log::debug;
+use serde_json::Value;
+use std::process::Stdio;
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+use crate::database::{EntrySource, Selection};
+
+/// Helpers that hang, e.g. on a clipboard owner that never answers, must not stall capturing
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Extra types browsers offer with a copy, holding the URL of the page
+const URL_MIME_TYPES: &[&str] = &["chromium/x-source-url", "text/x-moz-url-priv"];
+
+/// Find out where the current content of `selection` was copied from.
+///
+/// The focused window is asked from Hyprland, Sway or X11 (`_NET_ACTIVE_WINDOW`);
+/// other Wayland compositors don't expose it. The page URL comes from the
+/// selection's offer itself.
+pub async fn detect(selection: Selection) -> EntrySource {
+    let window = focused_window().await.unwrap_or_default();
+    let mime_types = offered_types(selection).await;
+    let url = source_url(selection, &mime_types).await;
+
+    EntrySource { app: window.app, window_title: window.title, url, selection, pid: window.pid, mime_types }
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    app: Option<String>,
+    title: Option<String>,
+    pid: Option<u32>,
+}
+
+async fn focused_window() -> Option<Window> {
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        let window: Value = serde_json::from_slice(&run("hyprctl", &["activewindow", "-j"]).await?).ok()?;
+        return Some(Window { app: json_string(&window, "class"), title: json_string(&window, "title"), pid: json_pid(&window) });
+    }
+
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let tree: Value = serde_json::from_slice(&run("swaymsg", &["-t", "get_tree", "-r"]).await?).ok()?;
+        let node = find_focused(&tree)?;
+        // XWayland windows have no app_id, only an X11 class
+        let app = json_string(node, "app_id").or_else(|| {
+            node.pointer("/window_properties/class").and_then(Value::as_str).map(String::from)
+        });
+        return Some(Window { app, title: json_string(node, "name"), pid: json_pid(node) });
+    }
+
+    // Under XWayland the X11 active window ignores native Wayland windows
+    if std::env::var_os("DISPLAY").is_some() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        return x11_active_window().await;
+    }
+
+    None
+}
+
+async fn x11_active_window() -> Option<Window> {
+    // _NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007
+    let root = String::from_utf8(run("xprop", &["-root", "_NET_ACTIVE_WINDOW"]).await?).ok()?;
+    let id = root.split_whitespace().last()?.trim_end_matches(',');
+    if !id.starts_with("0x") || id == "0x0" {
+        return None;
+    }
+
+    let properties = String::from_utf8(run("xprop", &["-id", id, "WM_CLASS", "_NET_WM_NAME", "_NET_WM_PID"]).await?).ok()?;
+    let mut window = Window::default();
+    for line in properties.lines() {
+        // WM_CLASS(STRING) = "instance", "Class"
+        if line.starts_with("WM_CLASS") {
+            window.app = quoted_values(line).pop();
+        } else if line.starts_with("_NET_WM_NAME") {
+            window.title = quoted_values(line).into_iter().next();
+        } else if line.starts_with("_NET_WM_PID") {
+            // _NET_WM_PID(CARDINAL) = 4242
+            window.pid = line.rsplit(' ').next().and_then(|pid| pid.parse().ok());
+        }
+    }
+    Some(window)
+}
+
+/// MIME types (X11 targets) the selection is offered as, empty when it is
+/// empty or neither wl-paste nor xclip is there to ask
+async fn offered_types(selection: Selection) -> Vec<String> {
+    let primary = selection == Selection::Primary;
+    let types = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let args: &[&str] = if primary { &["--primary", "--list-types"] } else { &["--list-types"] };
+        run("wl-paste", args).await
+    } else {
+        let x11_selection = if primary { "primary" } else { "clipboard" };
+        run("xclip", &["-selection", x11_selection, "-o", "-t", "TARGETS"]).await
+    };
+
+    let Some(types) = types else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&types)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+async fn source_url(selection: Selection, types: &[String]) -> Option<String> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let primary = selection == Selection::Primary;
+    let x11_selection = if primary { "primary" } else { "clipboard" };
+
+    let mime = URL_MIME_TYPES.iter().find(|mime| types.iter().any(|offered| offered == **mime))?;
+
+    let data = if wayland {
+        let mut args = vec!["--no-newline", "--type", mime];
+        if primary {
+            args.push("--primary");
+        }
+        run("wl-paste", &args).await?
+    } else {
+        run("xclip", &["-selection", x11_selection, "-o", "-t", mime]).await?
+    };
+
+    let text = decode_text(&data);
+    let url = text.lines().next()?.trim();
+    (!url.is_empty()).then(|| url.to_string())
+}
+
+/// Run a helper and return its output, or None if it is missing, fails or hangs
+async fn run(program: &str, args: &[&str]) -> Option<Vec<u8>> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    match timeout(COMMAND_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => Some(output.stdout),
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => {
+            debug!("Could not run {}: {}", program, e);
+            None
+        }
+        Err(_) => {
+            debug!("{} timed out", program);
+            None
+        }
+    }
+}
+
+fn json_pid(value: &Value) -> Option<u32> {
+    value.get("pid").and_then(Value::as_u64).and_then(|pid| u32::try_from(pid).ok()).filter(|pid| *pid > 0)
+}
+
+fn json_string(value: &Value, key: &str) -> Option<String> {
+    value.get(key).and_then(Value::as_str).filter(|s| !s.is_empty()).map(String::from)
+}
+
+/// The focused window in a Sway layout tree
+fn find_focused(node: &Value) -> Option<&Value> {
+    if node.get("focused").and_then(Value::as_bool) == Some(true) {
+        return Some(node);
+    }
+
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node.get(key).and_then(Value::as_array))
+        .flatten()
+        .find_map(find_focused)
+}
+
+/// The double-quoted strings in an xprop line
+fn quoted_values(line: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, current.as_mut()) {
+            ('"', None) => current = Some(String::new()),
+            ('"', Some(_)) => values.extend(current.take()),
+            ('\\', Some(value)) => value.extend(chars.next()),
+            (c, Some(value)) => value.push(c),
+            _ => {}
+        }
+    }
+    values
+}
+
+/// Firefox offers its URL type as UTF-16, other browsers as UTF-8
+fn decode_text(data: &[u8]) -> String {
+    if data.len() >= 2 && data.chunks_exact(2).remainder().is_empty() && data.iter().skip(1).step_by(2).all(|b| *b == 0) {
+        let units: Vec<u16> = data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        return String::from_utf16_lossy(&units);
+    }
+    String::from_utf8_lossy(data).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_xprop_values() {
+        let line = r#"_NET_WM_NAME(UTF8_STRING) = "Say \"hi\" - Mozilla Firefox""#;
+        assert_eq!(quoted_values(line), vec![r#"Say "hi" - Mozilla Firefox"#]);
+        assert_eq!(quoted_values(r#"WM_CLASS(STRING) = "Navigator", "firefox""#).pop().unwrap(), "firefox");
+    }
+
+    #[test]
+    fn test_find_focused_sway_window() {
+        let tree = serde_json::json!({
+            "focused": false,
+            "nodes": [{
+                "focused": false,
+                "nodes": [{"focused": false, "app_id": "foot"}],
+                "floating_nodes": [{"focused": true, "app_id": "org.gnome.Nautilus", "name": "Files"}]
+            }]
+        });
+
+        let node = find_focused(&tree).unwrap();
+        assert_eq!(json_string(node, "app_id").as_deref(), Some("org.gnome.Nautilus"));
+    }
+
+    #[test]
+    fn test_decode_browser_url() {
+        let utf16: Vec<u8> = "https://example.com\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(decode_text(&utf16), "https://example.com\n");
+        assert_eq!(decode_text(b"https://example.org"), "https://example.org");
+    }
+}
@@ -2,9 +2,38 @@
 
 CREATE TABLE IF NOT EXISTS clipboard_history (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
-    content TEXT NOT NULL UNIQUE,
+    -- For entries kept in a blob file, only the first 4 KiB
+    content TEXT NOT NULL,
     timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-    is_default BOOLEAN NOT NULL DEFAULT FALSE
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Times the entry was copied or selected, for frecency ranking
+    use_count INTEGER NOT NULL DEFAULT 1,
+    last_used DATETIME,
+    -- Where the entry was copied from, when the desktop exposes it
+    source_app TEXT,
+    window_title TEXT,
+    source_url TEXT,
+    -- Detected kind of content (url, email, path, color, number, json, code,
+    -- command or text) and, for code, the guessed language
+    content_type TEXT,
+    language TEXT,
+    -- Selection the entry was captured from: clipboard or primary
+    selection TEXT NOT NULL DEFAULT 'clipboard',
+    -- SHA-256 of the content as normalized by the dedup policy (whitespace,
+    -- line endings, Unicode), which entries are deduplicated by
+    content_hash TEXT,
+    -- File under blobs/ next to the database holding the content of large
+    -- entries (named by content_hash), NULL when it is stored in the row
+    blob TEXT,
+    -- When the daemon removes the entry, NULL for entries kept until cleanup
+    expires_at DATETIME,
+    -- Imported from another clipboard manager or restored from a backup,
+    -- which keeps the entry out of the cleanup of old entries until the
+    -- history is cleared
+    imported BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Name of the machine a shared entry was received from, NULL for entries
+    -- copied here
+    origin_host TEXT
 );
 
 -- Index for efficient timestamp-based queries
@@ -13,6 +42,77 @@ CREATE INDEX IF NOT EXISTS idx_timestamp ON clipboard_history(timestamp DESC);
 -- Index for finding the default entry quickly
 CREATE INDEX IF NOT EXISTS idx_default ON clipboard_history(is_default);
 
+-- Index for deduplicating copies without comparing their full text
+CREATE UNIQUE INDEX IF NOT EXISTS idx_content_hash ON clipboard_history(content_hash);
+
+-- Index for finding expired entries, which are few
+CREATE INDEX IF NOT EXISTS idx_expires_at ON clipboard_history(expires_at) WHERE expires_at IS NOT NULL;
+
+-- Entries queued for sequential pasting, lowest position first
+CREATE TABLE IF NOT EXISTS paste_stack (
+    position INTEGER PRIMARY KEY AUTOINCREMENT,
+    entry_id INTEGER NOT NULL REFERENCES clipboard_history(id) ON DELETE CASCADE
+);
+
+-- Saved snippets, kept apart from the history and never cleaned up
+CREATE TABLE IF NOT EXISTS snippets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    folder TEXT NOT NULL DEFAULT '',
+    keyword TEXT UNIQUE,
+    content TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (folder, name)
+);
+
+-- Database-wide settings, such as the dedup policy the hashes were made with
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+-- Tags for grouping history entries; tagged entries are kept by the cleanup
+CREATE TABLE IF NOT EXISTS tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE
+);
+
+CREATE TABLE IF NOT EXISTS entry_tags (
+    entry_id INTEGER NOT NULL REFERENCES clipboard_history(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (entry_id, tag_id)
+);
+
+-- Ids of entries shared with other machines and the latest change seen of
+-- each (a Lamport clock and the machine that made it). entry_id is NULL once
+-- the entry was removed, so older changes don't bring it back. An entry
+-- copied on two machines at once is known under both uuids.
+CREATE TABLE IF NOT EXISTS sync_entries (
+    uuid TEXT PRIMARY KEY,
+    entry_id INTEGER,
+    clock INTEGER NOT NULL,
+    machine TEXT NOT NULL,
+    fingerprint TEXT  -- hash of the entry as last sent or received
+);
+
+CREATE INDEX IF NOT EXISTS idx_sync_entry ON sync_entries(entry_id);
+
+-- How far the change log of each other machine has been read, in bytes
+CREATE TABLE IF NOT EXISTS sync_logs (
+    machine TEXT PRIMARY KEY,
+    offset INTEGER NOT NULL
+);
+
+-- Every change to the history bumps the history_version setting, so history
+-- sync can tell there is nothing new to send. The same trigger exists for
+-- UPDATE and DELETE on clipboard_history and INSERT and DELETE on entry_tags.
+CREATE TRIGGER IF NOT EXISTS clipboard_history_insert_version AFTER INSERT ON clipboard_history
+BEGIN
+    INSERT INTO settings (key, value) VALUES ('history_version', '1')
+    ON CONFLICT (key) DO UPDATE SET value = CAST(value AS INTEGER) + 1;
+END;
+
 -- Sample queries:
 
 -- Get the most recent 10 entries
@@ -0,0 +1,302 @@
+use anyhow::Result;
+
+use crate::classify::ContentType;
+
+/// What the popup should do once a quick action has run
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionOutcome {
+    /// Open a URI with the default application (browser, mail client, file manager)
+    Open(String),
+    /// Show a `file://` URI selected in the file manager
+    Reveal(String),
+    /// Paste new text, which is kept as a history entry
+    Paste(String),
+}
+
+type ActionFn = Box<dyn Fn(&str) -> Result<ActionOutcome> + Send + Sync>;
+
+/// An action offered for entries of one content type
+pub struct QuickAction {
+    pub name: String,
+    pub label: String,
+    pub content_type: ContentType,
+    run: ActionFn,
+}
+
+impl QuickAction {
+    pub fn run(&self, content: &str) -> Result<ActionOutcome> {
+        (self.run)(content.trim())
+    }
+}
+
+/// Registry of the quick actions, keyed by the content type they apply to
+pub struct ActionRegistry {
+    actions: Vec<QuickAction>,
+}
+
+impl ActionRegistry {
+    /// Registry holding the built-in actions
+    pub fn builtin() -> Self {
+        let mut registry = Self { actions: Vec::new() };
+
+        registry.register(ContentType::Url, "open_url", "Open in browser", |s| {
+            let url = if s.contains("://") { s.to_string() } else { format!("https://{}", s) };
+            Ok(ActionOutcome::Open(url))
+        });
+        registry.register(ContentType::Email, "compose_email", "Compose email", |s| {
+            let address = s.strip_prefix("mailto:").unwrap_or(s);
+            Ok(ActionOutcome::Open(format!("mailto:{}", address)))
+        });
+        registry.register(ContentType::Path, "open_path", "Open", |s| Ok(ActionOutcome::Open(file_uri(s)?)));
+        registry.register(ContentType::Path, "reveal_path", "Show in file manager", |s| {
+            Ok(ActionOutcome::Reveal(file_uri(s)?))
+        });
+        registry.register(ContentType::Color, "color_hex", "Convert to hex", |s| {
+            Ok(ActionOutcome::Paste(parse_color(s)?.to_hex()))
+        });
+        registry.register(ContentType::Color, "color_rgb", "Convert to rgb()", |s| {
+            Ok(ActionOutcome::Paste(parse_color(s)?.to_rgb()))
+        });
+        registry.register(ContentType::Color, "color_hsl", "Convert to hsl()", |s| {
+            Ok(ActionOutcome::Paste(parse_color(s)?.to_hsl()))
+        });
+        registry.register(ContentType::Json, "json_format", "Format JSON", |s| {
+            let value: serde_json::Value = serde_json::from_str(s)?;
+            Ok(ActionOutcome::Paste(serde_json::to_string_pretty(&value)?))
+        });
+
+        registry
+    }
+
+    /// Add an action, replacing any existing one with the same name
+    pub fn register<F>(&mut self, content_type: ContentType, name: &str, label: &str, run: F)
+    where
+        F: Fn(&str) -> Result<ActionOutcome> + Send + Sync + 'static,
+    {
+        self.actions.retain(|a| a.name != name);
+        self.actions.push(QuickAction {
+            name: name.to_string(),
+            label: label.to_string(),
+            content_type,
+            run: Box::new(run),
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&QuickAction> {
+        self.actions.iter().find(|a| a.name == name)
+    }
+
+    /// The actions for entries of `content_type`, in registration order
+    pub fn for_type(&self, content_type: ContentType) -> Vec<&QuickAction> {
+        self.actions.iter().filter(|a| a.content_type == content_type).collect()
+    }
+}
+
+/// A `file://` URI for a copied path, expanding `~/`
+fn file_uri(path: &str) -> Result<String> {
+    if path.starts_with("file://") {
+        return Ok(path.to_string());
+    }
+
+    let path = match path.strip_prefix("~/") {
+        Some(rest) => {
+            let home = std::env::var("HOME").map_err(|_| anyhow::anyhow!("HOME is not set"))?;
+            format!("{}/{}", home.trim_end_matches('/'), rest)
+        }
+        None => path.to_string(),
+    };
+    if !path.starts_with('/') {
+        anyhow::bail!("Only absolute paths can be opened");
+    }
+
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Ok(uri)
+}
+
+/// An sRGB color with alpha between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Color {
+    red: u8,
+    green: u8,
+    blue: u8,
+    alpha: f64,
+}
+
+impl Color {
+    fn to_hex(self) -> String {
+        let hex = format!("#{:02x}{:02x}{:02x}", self.red, self.green, self.blue);
+        if self.alpha < 1.0 {
+            format!("{}{:02x}", hex, (self.alpha * 255.0).round() as u8)
+        } else {
+            hex
+        }
+    }
+
+    fn to_rgb(self) -> String {
+        if self.alpha < 1.0 {
+            format!("rgba({}, {}, {}, {})", self.red, self.green, self.blue, round2(self.alpha))
+        } else {
+            format!("rgb({}, {}, {})", self.red, self.green, self.blue)
+        }
+    }
+
+    fn to_hsl(self) -> String {
+        let (r, g, b) = (self.red as f64 / 255.0, self.green as f64 / 255.0, self.blue as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let lightness = (max + min) / 2.0;
+        let delta = max - min;
+
+        let (hue, saturation) = if delta == 0.0 {
+            (0.0, 0.0)
+        } else {
+            let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+            let hue = if max == r {
+                ((g - b) / delta).rem_euclid(6.0)
+            } else if max == g {
+                (b - r) / delta + 2.0
+            } else {
+                (r - g) / delta + 4.0
+            };
+            (hue * 60.0, saturation)
+        };
+
+        let (h, s, l) = (hue.round(), (saturation * 100.0).round(), (lightness * 100.0).round());
+        if self.alpha < 1.0 {
+            format!("hsla({}, {}%, {}%, {})", h, s, l, round2(self.alpha))
+        } else {
+            format!("hsl({}, {}%, {}%)", h, s, l)
+        }
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Parse `#rgb[a]`, `#rrggbb[aa]`, `rgb[a](...)` and `hsl[a](...)` colors
+fn parse_color(text: &str) -> Result<Color> {
+    let invalid = || anyhow::anyhow!("'{}' is not a color", text);
+
+    if let Some(hex) = text.strip_prefix('#') {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        let channels: Vec<u8> = match digits.len() {
+            3 | 4 => digits.iter().map(|d| d * 17).collect(),
+            6 | 8 => digits.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect(),
+            _ => return Err(invalid()),
+        };
+        return Ok(Color {
+            red: channels[0],
+            green: channels[1],
+            blue: channels[2],
+            alpha: channels.get(3).map_or(1.0, |a| *a as f64 / 255.0),
+        });
+    }
+
+    let lower = text.to_ascii_lowercase();
+    let (function, arguments) = lower.split_once('(').ok_or_else(invalid)?;
+    let arguments = arguments.strip_suffix(')').ok_or_else(invalid)?;
+    // Both "rgb(1, 2, 3)" and the newer "rgb(1 2 3 / 50%)" syntax
+    let values: Vec<&str> = arguments.split([',', ' ', '/']).filter(|v| !v.is_empty()).collect();
+    if values.len() != 3 && values.len() != 4 {
+        return Err(invalid());
+    }
+
+    let number = |value: &str| -> Result<f64> {
+        let value = value.trim_end_matches("deg").trim_end_matches('%');
+        value.parse::<f64>().map_err(|_| invalid())
+    };
+    let alpha = match values.get(3) {
+        Some(value) if value.ends_with('%') => number(value)? / 100.0,
+        Some(value) => number(value)?,
+        None => 1.0,
+    }
+    .clamp(0.0, 1.0);
+
+    let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let (red, green, blue) = match function.trim() {
+        "rgb" | "rgba" => {
+            let component = |value: &str| -> Result<u8> {
+                if value.ends_with('%') {
+                    Ok(channel(number(value)? / 100.0))
+                } else {
+                    Ok(channel(number(value)? / 255.0))
+                }
+            };
+            (component(values[0])?, component(values[1])?, component(values[2])?)
+        }
+        "hsl" | "hsla" => {
+            let hue = number(values[0])?.rem_euclid(360.0) / 60.0;
+            let saturation = (number(values[1])? / 100.0).clamp(0.0, 1.0);
+            let lightness = (number(values[2])? / 100.0).clamp(0.0, 1.0);
+
+            let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+            let x = chroma * (1.0 - (hue.rem_euclid(2.0) - 1.0).abs());
+            let (r, g, b) = match hue as u32 {
+                0 => (chroma, x, 0.0),
+                1 => (x, chroma, 0.0),
+                2 => (0.0, chroma, x),
+                3 => (0.0, x, chroma),
+                4 => (x, 0.0, chroma),
+                _ => (chroma, 0.0, x),
+            };
+            let m = lightness - chroma / 2.0;
+            (channel(r + m), channel(g + m), channel(b + m))
+        }
+        _ => return Err(invalid()),
+    };
+
+    Ok(Color { red, green, blue, alpha })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(registry: &ActionRegistry, name: &str, content: &str) -> ActionOutcome {
+        registry.get(name).unwrap().run(content).unwrap()
+    }
+
+    #[test]
+    fn test_actions_are_keyed_by_type() {
+        let registry = ActionRegistry::builtin();
+        let names: Vec<&str> = registry.for_type(ContentType::Path).iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["open_path", "reveal_path"]);
+        assert!(registry.for_type(ContentType::Text).is_empty());
+
+        assert_eq!(run(&registry, "open_url", "www.example.org"), ActionOutcome::Open("https://www.example.org".into()));
+        assert_eq!(run(&registry, "compose_email", "a@b.io\n"), ActionOutcome::Open("mailto:a@b.io".into()));
+        assert_eq!(
+            run(&registry, "reveal_path", "/tmp/my report.pdf"),
+            ActionOutcome::Reveal("file:///tmp/my%20report.pdf".into())
+        );
+        assert!(registry.get("open_path").unwrap().run("./relative").is_err());
+    }
+
+    #[test]
+    fn test_convert_colors() {
+        let registry = ActionRegistry::builtin();
+        let paste = |name: &str, content: &str| match run(&registry, name, content) {
+            ActionOutcome::Paste(text) => text,
+            other => panic!("unexpected outcome {:?}", other),
+        };
+
+        assert_eq!(paste("color_rgb", "#1E90FF"), "rgb(30, 144, 255)");
+        assert_eq!(paste("color_hsl", "#1e90ff"), "hsl(210, 100%, 56%)");
+        assert_eq!(paste("color_hex", "rgb(30, 144, 255)"), "#1e90ff");
+        assert_eq!(paste("color_hex", "hsl(210, 100%, 50%)"), "#0080ff");
+        assert_eq!(paste("color_rgb", "#f008"), "rgba(255, 0, 0, 0.53)");
+        assert_eq!(paste("color_hsl", "rgb(0 0 0 / 50%)"), "hsla(0, 0%, 0%, 0.5)");
+        assert!(registry.get("color_hex").unwrap().run("rgb(1, 2)").is_err());
+    }
+}
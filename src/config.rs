@@ -0,0 +1,352 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::database::DedupPolicy;
+
+/// User configuration, read from `~/.config/clippoo/config.json`.
+///
+/// Every field has a default, so the file only needs the options being changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub type_out: TypeOutConfig,
+    pub paste_stack: PasteStackConfig,
+    pub merge: MergeConfig,
+    pub history: HistoryConfig,
+    pub hooks: HooksConfig,
+    pub plugins: PluginsConfig,
+    pub primary: PrimaryConfig,
+    pub sync: SyncConfig,
+    pub clipboard: ClipboardConfig,
+    pub storage: StorageConfig,
+    /// Differences ignored when deduplicating copies, see `database.rs`
+    pub dedup: DedupPolicy,
+    pub backup: BackupConfig,
+    pub history_sync: HistorySyncConfig,
+    pub share: ShareConfig,
+    pub expiry: ExpiryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TypeOutConfig {
+    /// Delay between synthesized keystrokes, in milliseconds
+    pub delay_ms: u64,
+}
+
+impl Default for TypeOutConfig {
+    fn default() -> Self {
+        Self { delay_ms: 12 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasteStackConfig {
+    /// Push every newly copied entry onto the paste stack
+    pub collect_copies: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MergeConfig {
+    pub separator: MergeSeparator,
+    pub order: MergeOrder,
+}
+
+/// Text placed between entries when merging them into one paste
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeSeparator {
+    #[default]
+    Newline,
+    Comma,
+    Space,
+    Custom(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeOrder {
+    /// The order in which the rows were selected
+    #[default]
+    Selection,
+    /// Oldest entry first
+    Chronological,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// How the popup orders the history when it opens
+    pub sort: HistorySort,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistorySort {
+    /// Most recently copied first
+    #[default]
+    Recent,
+    /// Entries used often and lately first
+    Frecency,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrimaryConfig {
+    /// Also record the PRIMARY selection (selected text, pasted with a middle click)
+    pub enabled: bool,
+    /// How long a selection must stay unchanged before it is recorded, in milliseconds
+    pub debounce_ms: u64,
+}
+
+impl Default for PrimaryConfig {
+    fn default() -> Self {
+        Self { enabled: false, debounce_ms: 750 }
+    }
+}
+
+/// Keeping CLIPBOARD and PRIMARY in sync, for apps that only read one of them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub direction: SyncDirection,
+    /// Leave a selection alone while it holds an image instead of text
+    pub text_only: bool,
+    /// Selections shorter than this many characters are not copied to CLIPBOARD
+    pub min_length: usize,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self { direction: SyncDirection::default(), text_only: true, min_length: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncDirection {
+    #[default]
+    Off,
+    /// Copied text is also put in PRIMARY
+    ClipboardToPrimary,
+    /// Selected text is also put in CLIPBOARD
+    PrimaryToClipboard,
+    Both,
+}
+
+impl SyncDirection {
+    pub fn to_primary(self) -> bool {
+        matches!(self, SyncDirection::ClipboardToPrimary | SyncDirection::Both)
+    }
+
+    pub fn to_clipboard(self) -> bool {
+        matches!(self, SyncDirection::PrimaryToClipboard | SyncDirection::Both)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// When the daemon takes over the clipboard from the app that copied
+    pub keep_alive: KeepAlive,
+    /// Put the default entry back on an empty clipboard when the daemon starts
+    pub restore_on_start: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepAlive {
+    /// Leave the clipboard to the apps
+    #[default]
+    Off,
+    /// Offer the default entry again once the app that owned the clipboard
+    /// exited, but not when it emptied the clipboard itself
+    WhenEmptied,
+    /// Take over every plain text copy right away
+    Always,
+}
+
+/// Size limits for stored content, all in bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Larger copies are not stored at all
+    pub max_size: usize,
+    /// Larger entries are stored but the popup search doesn't look inside them
+    pub search_limit: usize,
+    /// Larger entries are kept in files next to the database instead of in it
+    pub blob_threshold: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self { max_size: 64 * 1024 * 1024, search_limit: 1024 * 1024, blob_threshold: 256 * 1024 }
+    }
+}
+
+/// Rolling backups written by the daemon next to the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    /// Hours between two backups
+    pub interval_hours: u64,
+    /// Number of backups kept, older ones are deleted
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self { enabled: false, interval_hours: 24, keep: 7 }
+    }
+}
+
+/// Merging the history with other machines through a shared directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistorySyncConfig {
+    /// Directory every machine writes its change log to; sync is off when unset
+    pub dir: Option<PathBuf>,
+    /// Seconds between two syncs
+    pub interval_secs: u64,
+}
+
+impl Default for HistorySyncConfig {
+    fn default() -> Self {
+        Self { dir: None, interval_secs: 30 }
+    }
+}
+
+/// Live sharing of new copies with other daemons on the network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShareConfig {
+    pub enabled: bool,
+    /// Name the other machines record as the origin of entries shared from
+    /// this one; the host name when unset
+    pub name: Option<String>,
+    /// Secret every sharing machine knows, see `clippoo-daemon share-key`
+    pub key: String,
+    /// TCP port to listen for peers on
+    pub port: u16,
+    /// Peers given as "host:port", on top of the ones found with mDNS
+    pub peers: Vec<String>,
+    /// Find peers on the local network with mDNS
+    pub discover: bool,
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        Self { enabled: false, name: None, key: String::new(), port: 7315, peers: Vec::new(), discover: true }
+    }
+}
+
+/// Entries that remove themselves from the history after a while
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExpiryConfig {
+    /// Seconds to keep entries of a content type ("url", "code", ...) or of a
+    /// sensitive class ("otp", "secret"), which takes precedence
+    pub after: HashMap<String, u64>,
+}
+
+impl ExpiryConfig {
+    /// Seconds to keep `content` of the given type, if it expires at all
+    pub fn time_to_live(&self, content: &str, content_type: &str) -> Option<u64> {
+        crate::classify::sensitive_class(content)
+            .and_then(|class| self.after.get(class))
+            .or_else(|| self.after.get(content_type))
+            .copied()
+    }
+}
+
+/// Commands run on clipboard events, see `hooks.rs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Run before a copy is stored; they can veto or rewrite it
+    pub on_capture: Vec<HookCommand>,
+    /// Run when an entry is picked in the popup
+    pub on_select: Vec<HookCommand>,
+    /// Run after `clippoo-daemon clear`
+    pub on_clear: Vec<HookCommand>,
+    /// Time limit for each hook, in milliseconds
+    pub timeout_ms: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_capture: Vec::new(),
+            on_select: Vec::new(),
+            on_clear: Vec::new(),
+            timeout_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookCommand {
+    /// Program and its arguments, run without a shell
+    pub command: Vec<String>,
+    /// Overrides `hooks.timeout_ms` for this hook
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// Limits for the Rhai plugins in `~/.config/clippoo/plugins/`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginsConfig {
+    pub enabled: bool,
+    /// Time a plugin may run for each call, in milliseconds
+    pub time_budget_ms: u64,
+    /// Longest string a plugin may build, in bytes
+    pub max_string_size: usize,
+    /// Most items an array or map built by a plugin may hold
+    pub max_collection_size: usize,
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            time_budget_ms: 50,
+            max_string_size: 8 * 1024 * 1024,
+            max_collection_size: 10_000,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let path = Self::get_config_path()?;
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        let config = serde_json::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    /// Load the configuration, falling back to defaults if it can't be read
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_else(|e| {
+            log::warn!("{}. Using default configuration", e);
+            Self::default()
+        })
+    }
+
+    fn get_config_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
+        Ok(config_dir.join("clippoo").join("config.json"))
+    }
+}
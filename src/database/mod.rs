@@ -1,8 +1,41 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::{SqliteConnection, SqlitePool, SqlitePoolOptions};
+use sqlx::Connection;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+pub mod sync;
+
+/// Bytes of a blob entry kept in the database, so SQL queries still see its start
+const BLOB_PREVIEW: usize = 4096;
+
+/// Unused blob files younger than this are kept, since the daemon, popup and
+/// CLI share the blob directory and write a file before its entry is committed
+const BLOB_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// Columns of the history table, also used to rebuild tables from older releases
+const HISTORY_COLUMNS: &str = "
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    content TEXT NOT NULL,
+    timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    use_count INTEGER NOT NULL DEFAULT 1,
+    last_used DATETIME,
+    source_app TEXT,
+    window_title TEXT,
+    source_url TEXT,
+    content_type TEXT,
+    language TEXT,
+    selection TEXT NOT NULL DEFAULT 'clipboard',
+    content_hash TEXT,
+    blob TEXT,
+    expires_at DATETIME,
+    imported BOOLEAN NOT NULL DEFAULT FALSE,
+    origin_host TEXT";
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct ClipboardEntry {
@@ -10,16 +43,170 @@ pub struct ClipboardEntry {
     pub content: String,
     pub timestamp: DateTime<Utc>,
     pub is_default: bool,
+    /// Application id (Wayland app_id or X11 WM_CLASS) the entry was copied from
+    pub source_app: Option<String>,
+    pub window_title: Option<String>,
+    /// Page the entry was copied from, if a browser offered it
+    pub source_url: Option<String>,
+    /// Kind of content (url, email, code, ...), set by the daemon
+    pub content_type: Option<String>,
+    /// Language guessed for code
+    pub language: Option<String>,
+    pub selection: Selection,
+    /// File under `blobs/` holding the content when it was too large for the
+    /// database. `content` has already been read from it.
+    #[serde(skip)]
+    #[sqlx(default)]
+    pub blob: Option<String>,
+    /// When the entry removes itself from the history
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Machine the entry was shared from, `None` for entries copied here
+    #[serde(default)]
+    pub origin_host: Option<String>,
+}
+
+/// The X11/Wayland selection an entry was captured from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Selection {
+    /// Explicit copies (Ctrl+C)
+    #[default]
+    Clipboard,
+    /// Selected text, pasted with a middle click
+    Primary,
+}
+
+impl Selection {
+    pub fn name(self) -> &'static str {
+        match self {
+            Selection::Clipboard => "clipboard",
+            Selection::Primary => "primary",
+        }
+    }
+}
+
+/// Where captured content was copied from, as far as the desktop exposes it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntrySource {
+    pub app: Option<String>,
+    pub window_title: Option<String>,
+    pub url: Option<String>,
+    pub selection: Selection,
+    /// Process of the focused window, usually the one that copied. Not stored.
+    pub pid: Option<u32>,
+    /// MIME types the selection was offered as. Not stored.
+    pub mime_types: Vec<String>,
+}
+
+/// Differences ignored when looking for duplicates of new content. A copy
+/// that only differs from an entry in these ways replaces that entry's content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DedupPolicy {
+    /// Leading and trailing whitespace, such as a final newline
+    pub trim: bool,
+    /// CRLF and CR line endings versus LF
+    pub line_endings: bool,
+    /// Different Unicode encodings of the same text (compared in NFC)
+    pub unicode: bool,
+}
+
+impl Default for DedupPolicy {
+    fn default() -> Self {
+        Self { trim: true, line_endings: true, unicode: true }
+    }
+}
+
+impl DedupPolicy {
+    /// Only identical content is a duplicate
+    pub fn exact() -> Self {
+        Self { trim: false, line_endings: false, unicode: false }
+    }
+
+    /// The form of `content` that duplicates are detected by
+    pub fn normalize(&self, content: &str) -> String {
+        let mut text = content.to_string();
+        if self.line_endings {
+            text = text.replace("\r\n", "\n").replace('\r', "\n");
+        }
+        if self.trim {
+            text = text.trim().to_string();
+        }
+        if self.unicode {
+            text = text.nfc().collect();
+        }
+        text
+    }
+}
+
+/// A history entry with its usage and tags, as written to backups
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct EntryRecord {
+    /// Row id in the database the entry was exported from
+    #[serde(skip)]
+    pub id: i64,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub use_count: i64,
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub source_app: Option<String>,
+    #[serde(default)]
+    pub window_title: Option<String>,
+    #[serde(default)]
+    pub source_url: Option<String>,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub selection: Selection,
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub tags: Vec<String>,
+    /// Blob file of a large entry; `content` has been read from it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_host: Option<String>,
+}
+
+/// Reusable text kept apart from the clipboard history and its cleanup
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Snippet {
+    pub id: i64,
+    pub name: String,
+    pub folder: String,
+    pub keyword: Option<String>,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 pub struct Database {
     pool: SqlitePool,
+    path: PathBuf,
+    /// Directory next to the database holding large entries
+    blob_dir: PathBuf,
+    /// Content larger than this, in bytes, is stored in `blob_dir`
+    blob_threshold: usize,
+    /// Policy the stored content hashes were computed with
+    dedup: DedupPolicy,
 }
 
 impl Database {
     pub async fn new() -> Result<Self> {
-        let db_path = Self::get_db_path()?;
+        Self::open(&Self::get_db_path()?).await
+    }
 
+    /// Open (or create) the history database at a specific path
+    pub async fn open(db_path: &Path) -> Result<Self> {
         // Ensure directory exists
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -27,7 +214,7 @@ impl Database {
 
         // Touch the file to ensure it exists
         if !db_path.exists() {
-            std::fs::File::create(&db_path)?;
+            std::fs::File::create(db_path)?;
         }
 
         let db_url = format!("sqlite:{}", db_path.display());
@@ -37,11 +224,30 @@ impl Database {
             .connect(&db_url)
         .await?;
 
-        let db = Self { pool };
+        let blob_dir = db_path.with_file_name("blobs");
+        let mut db = Self {
+            pool,
+            path: db_path.to_path_buf(),
+            blob_dir,
+            blob_threshold: usize::MAX,
+            dedup: DedupPolicy::exact(),
+        };
         db.init_schema().await?;
+        db.dedup = db.stored_dedup_policy().await?;
         Ok(db)
     }
 
+    /// Location of the database file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Store content larger than `bytes` in files next to the database
+    pub fn with_blob_threshold(mut self, bytes: usize) -> Self {
+        self.blob_threshold = bytes;
+        self
+    }
+
     fn get_db_path() -> Result<PathBuf> {
         let data_dir = dirs::data_local_dir()
             .ok_or_else(|| anyhow::anyhow!("Could not find local data directory"))?;
@@ -49,66 +255,510 @@ impl Database {
     }
 
     async fn init_schema(&self) -> Result<()> {
+        sqlx::query(&format!("CREATE TABLE IF NOT EXISTS clipboard_history ({})", HISTORY_COLUMNS))
+            .execute(&self.pool)
+        .await?;
+
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS clipboard_history (
+            CREATE TABLE IF NOT EXISTS paste_stack (
+                position INTEGER PRIMARY KEY AUTOINCREMENT,
+                entry_id INTEGER NOT NULL REFERENCES clipboard_history(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS snippets (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
-                content TEXT NOT NULL UNIQUE,
-                timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                is_default BOOLEAN NOT NULL DEFAULT FALSE
+                name TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT '',
+                keyword TEXT UNIQUE,
+                content TEXT NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (folder, name)
             );
 
+            CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS tags (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE COLLATE NOCASE
+            );
+
+            CREATE TABLE IF NOT EXISTS entry_tags (
+                entry_id INTEGER NOT NULL REFERENCES clipboard_history(id) ON DELETE CASCADE,
+                tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+                PRIMARY KEY (entry_id, tag_id)
+            );
+
+            CREATE TABLE IF NOT EXISTS sync_entries (
+                uuid TEXT PRIMARY KEY,
+                entry_id INTEGER,
+                clock INTEGER NOT NULL,
+                machine TEXT NOT NULL,
+                fingerprint TEXT
+            );
+
+            CREATE TABLE IF NOT EXISTS sync_logs (
+                machine TEXT PRIMARY KEY,
+                offset INTEGER NOT NULL
+            );
+            "#,
+        )
+            .execute(&self.pool)
+        .await?;
+
+        // Columns added after the first release
+        self.ensure_column("clipboard_history", "use_count", "INTEGER NOT NULL DEFAULT 1").await?;
+        self.ensure_column("clipboard_history", "last_used", "DATETIME").await?;
+        self.ensure_column("clipboard_history", "source_app", "TEXT").await?;
+        self.ensure_column("clipboard_history", "window_title", "TEXT").await?;
+        self.ensure_column("clipboard_history", "source_url", "TEXT").await?;
+        self.ensure_column("clipboard_history", "content_type", "TEXT").await?;
+        self.ensure_column("clipboard_history", "language", "TEXT").await?;
+        self.ensure_column("clipboard_history", "selection", "TEXT NOT NULL DEFAULT 'clipboard'").await?;
+        self.ensure_column("clipboard_history", "content_hash", "TEXT").await?;
+        self.ensure_column("clipboard_history", "blob", "TEXT").await?;
+        self.ensure_column("clipboard_history", "expires_at", "DATETIME").await?;
+        self.ensure_column("clipboard_history", "imported", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+        self.ensure_column("clipboard_history", "origin_host", "TEXT").await?;
+        self.drop_content_unique().await?;
+        self.fill_content_hashes().await?;
+
+        sqlx::query(
+            r#"
             CREATE INDEX IF NOT EXISTS idx_timestamp ON clipboard_history(timestamp DESC);
             CREATE INDEX IF NOT EXISTS idx_default ON clipboard_history(is_default);
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_content_hash ON clipboard_history(content_hash);
+            CREATE INDEX IF NOT EXISTS idx_expires_at ON clipboard_history(expires_at) WHERE expires_at IS NOT NULL;
+            CREATE INDEX IF NOT EXISTS idx_sync_entry ON sync_entries(entry_id);
             "#,
         )
             .execute(&self.pool)
         .await?;
 
+        // Bumped on every change to the history, so history sync can tell
+        // there is nothing new to send without going through all of it
+        let changes = [
+            ("clipboard_history", "INSERT"),
+            ("clipboard_history", "UPDATE"),
+            ("clipboard_history", "DELETE"),
+            ("entry_tags", "INSERT"),
+            ("entry_tags", "DELETE"),
+        ];
+        for (table, event) in changes {
+            sqlx::query(&format!(
+                "CREATE TRIGGER IF NOT EXISTS {0}_{1}_version AFTER {2} ON {0}
+BEGIN
+    INSERT INTO settings (key, value) VALUES ('history_version', '1')
+    ON CONFLICT (key) DO UPDATE SET value = CAST(value AS INTEGER) + 1;
+END",
+                table,
+                event.to_lowercase(),
+                event
+            ))
+                .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Older releases made `content` UNIQUE, which indexes the full text of
+    /// every entry. Rebuild the table without it; entries are deduplicated by
+    /// `content_hash` instead.
+    async fn drop_content_unique(&self) -> Result<()> {
+        let unique = sqlx::query_as::<_, (String,)>(
+            "SELECT name FROM pragma_index_list('clipboard_history') WHERE origin = 'u'"
+        )
+            .fetch_optional(&self.pool)
+        .await?;
+        if unique.is_none() {
+            return Ok(());
+        }
+
+        // Dropping the old table must not cascade to the stack and tags
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+
+        let mut tx = conn.begin().await?;
+        let columns = "id, content, timestamp, is_default, use_count, last_used, source_app, window_title, \
+source_url, content_type, language, selection, content_hash, blob, expires_at, imported, origin_host";
+        sqlx::query(&format!("CREATE TABLE clipboard_history_new ({})", HISTORY_COLUMNS))
+            .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!(
+            "INSERT INTO clipboard_history_new ({0}) SELECT {0} FROM clipboard_history",
+            columns
+        ))
+            .execute(&mut *tx)
+        .await?;
+        sqlx::query("DROP TABLE clipboard_history").execute(&mut *tx).await?;
+        sqlx::query("ALTER TABLE clipboard_history_new RENAME TO clipboard_history")
+            .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await?;
         Ok(())
     }
 
-    pub async fn add_entry(&self, content: String) -> Result<()> {
-        // First, check if this content already exists
+    /// The dedup policy saved by `set_dedup_policy`. Databases from before
+    /// there was one were hashed without normalizing.
+    async fn stored_dedup_policy(&self) -> Result<DedupPolicy> {
+        let value = sqlx::query_as::<_, (String,)>("SELECT value FROM settings WHERE key = 'dedup'")
+            .fetch_optional(&self.pool)
+        .await?;
+
+        match value {
+            Some((json,)) => Ok(serde_json::from_str(&json)?),
+            None => Ok(DedupPolicy::exact()),
+        }
+    }
+
+    /// Change how duplicates are detected. The policy is saved in the database,
+    /// so that other processes opening it detect duplicates the same way.
+    ///
+    /// When it changes, entries are hashed again and entries that are now
+    /// duplicates are merged into the newest of them.
+    pub async fn set_dedup_policy(&mut self, policy: DedupPolicy) -> Result<()> {
+        if policy == self.dedup {
+            return Ok(());
+        }
+        self.dedup = policy;
+
+        let mut rows = sqlx::query_as::<_, (i64, String, Option<String>)>(
+            "SELECT id, content, blob FROM clipboard_history ORDER BY timestamp DESC, id DESC"
+        )
+            .fetch_all(&self.pool)
+        .await?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE clipboard_history SET content_hash = NULL")
+            .execute(&mut *tx)
+        .await?;
+
+        let mut kept: HashMap<String, i64> = HashMap::new();
+        for (id, content, blob) in rows.iter_mut() {
+            if let Some(full) = blob.as_deref().and_then(|blob| self.read_blob(blob)) {
+                *content = full;
+            }
+
+            let hash = self.dedup_hash(content);
+            match kept.get(&hash) {
+                Some(&newer) => fold_entry(&mut tx, *id, newer).await?,
+                None => {
+                    sqlx::query("UPDATE clipboard_history SET content_hash = ?1 WHERE id = ?2")
+                        .bind(&hash)
+                        .bind(*id)
+                        .execute(&mut *tx)
+                    .await?;
+                    kept.insert(hash, *id);
+                }
+            }
+        }
+
+        sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES ('dedup', ?1)")
+            .bind(serde_json::to_string(&self.dedup)?)
+            .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.remove_unused_blobs().await
+    }
+
+    /// The hash duplicates are detected by
+    fn dedup_hash(&self, content: &str) -> String {
+        content_hash(&self.dedup.normalize(content))
+    }
+
+    /// Hash entries stored before `content_hash` existed
+    async fn fill_content_hashes(&self) -> Result<()> {
+        let rows = sqlx::query_as::<_, (i64, String)>(
+            "SELECT id, content FROM clipboard_history WHERE content_hash IS NULL"
+        )
+            .fetch_all(&self.pool)
+        .await?;
+
+        for (id, content) in rows {
+            sqlx::query("UPDATE clipboard_history SET content_hash = ?1 WHERE id = ?2")
+                .bind(self.dedup_hash(&content))
+                .bind(id)
+                .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a column to a table created by an older version of the schema
+    async fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let columns = sqlx::query_as::<_, (String,)>("SELECT name FROM pragma_table_info(?1)")
+            .bind(table)
+            .fetch_all(&self.pool)
+        .await?;
+
+        if !columns.iter().any(|(name,)| name == column) {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Add captured content to the history and return the id of its row
+    pub async fn add_entry(&self, content: String) -> Result<i64> {
+        self.add_entry_with_source(content, &EntrySource::default()).await
+    }
+
+    /// Like `add_entry`, also recording where the content was copied from
+    pub async fn add_entry_with_source(&self, content: String, source: &EntrySource) -> Result<i64> {
+        // First, check if this content (or a near-duplicate of it) already exists
+        let hash = self.dedup_hash(&content);
         let existing = sqlx::query_as::<_, (i64,)>(
-            "SELECT id FROM clipboard_history WHERE content = ?1"
+            "SELECT id FROM clipboard_history WHERE content_hash = ?1"
         )
-            .bind(&content)
+            .bind(&hash)
             .fetch_optional(&self.pool)
         .await?;
 
         if let Some((id,)) = existing {
-            // Update timestamp for existing entry
+            // Update timestamp for existing entry. Content that is already the
+            // default was just selected, so set_default_entry counted that use and
+            // the focused window is where it is pasted, not where it came from.
+            // Content that was ever copied explicitly stays a clipboard entry.
+            // A near-duplicate replaces the content with its own form.
+            let (stored, blob) = self.store_content(&content)?;
             sqlx::query(
-                "UPDATE clipboard_history SET timestamp = CURRENT_TIMESTAMP WHERE id = ?1"
+                "UPDATE clipboard_history
+SET content = ?6,
+    blob = ?7,
+    timestamp = CURRENT_TIMESTAMP,
+    last_used = CURRENT_TIMESTAMP,
+    use_count = use_count + CASE WHEN is_default THEN 0 ELSE 1 END,
+    source_app = CASE WHEN is_default OR (?2 IS NULL AND ?4 IS NULL) THEN source_app ELSE ?2 END,
+    window_title = CASE WHEN is_default OR (?2 IS NULL AND ?4 IS NULL) THEN window_title ELSE ?3 END,
+    source_url = CASE WHEN is_default OR (?2 IS NULL AND ?4 IS NULL) THEN source_url ELSE ?4 END,
+    selection = CASE WHEN ?5 = 'clipboard' THEN ?5 ELSE selection END
+WHERE id = ?1"
             )
                 .bind(id)
+                .bind(&source.app)
+                .bind(&source.window_title)
+                .bind(&source.url)
+                .bind(source.selection)
+                .bind(&stored)
+                .bind(&blob)
                 .execute(&self.pool)
             .await?;
+
+            self.remove_unused_blobs().await?;
+            Ok(id)
         } else {
-            // Insert new entry and set as default
+            // Insert new entry, as the default if it was copied. A selection
+            // is not what the clipboard holds, so the default stays put.
+            let (stored, blob) = self.store_content(&content)?;
+            let is_default = source.selection == Selection::Clipboard;
             let mut tx = self.pool.begin().await?;
 
             // Clear current default
-            sqlx::query("UPDATE clipboard_history SET is_default = FALSE WHERE is_default = TRUE")
-                .execute(&mut *tx)
-            .await?;
+            if is_default {
+                sqlx::query("UPDATE clipboard_history SET is_default = FALSE WHERE is_default = TRUE")
+                    .execute(&mut *tx)
+                .await?;
+            }
 
-            // Insert new entry as default
-            sqlx::query(
-                "INSERT INTO clipboard_history (content, is_default) VALUES (?1, TRUE)"
+            let id = sqlx::query(
+                "INSERT INTO clipboard_history (content, is_default, last_used, source_app, window_title, source_url, selection, content_hash, blob)
+VALUES (?1, ?8, CURRENT_TIMESTAMP, ?2, ?3, ?4, ?5, ?6, ?7)"
             )
-                .bind(&content)
+                .bind(&stored)
+                .bind(&source.app)
+                .bind(&source.window_title)
+                .bind(&source.url)
+                .bind(source.selection)
+                .bind(&hash)
+                .bind(&blob)
+                .bind(is_default)
                 .execute(&mut *tx)
-            .await?;
+            .await?
+            .last_insert_rowid();
 
             tx.commit().await?;
 
             // Clean up old entries (keep only last 50)
             self.cleanup_old_entries().await?;
+
+            Ok(id)
         }
+    }
 
-        Ok(())
+    /// Add entries from another clipboard manager, oldest first, keeping their
+    /// timestamps. Duplicates are detected like in `add_entry`; an existing
+    /// entry keeps the newer of the two timestamps and its default flag.
+    /// Imported entries are left out of the cleanup of old entries, so they
+    /// stay until the history is cleared. Returns how many entries were new.
+    pub async fn import_entries(&self, entries: Vec<(String, DateTime<Utc>)>) -> Result<usize> {
+        let mut added = 0;
+
+        for (content, timestamp) in entries {
+            let timestamp = sql_timestamp(timestamp);
+            let hash = self.dedup_hash(&content);
+            let (stored, blob) = self.store_content(&content)?;
+
+            let updated = sqlx::query(
+                "UPDATE clipboard_history
+SET content = CASE WHEN ?2 > timestamp THEN ?3 ELSE content END,
+    blob = CASE WHEN ?2 > timestamp THEN ?4 ELSE blob END,
+    timestamp = MAX(timestamp, ?2),
+    imported = TRUE
+WHERE content_hash = ?1"
+            )
+                .bind(&hash)
+                .bind(&timestamp)
+                .bind(&stored)
+                .bind(&blob)
+                .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+            if updated == 0 {
+                sqlx::query(
+                    "INSERT INTO clipboard_history (content, timestamp, last_used, content_hash, blob, imported)
+VALUES (?1, ?2, ?2, ?3, ?4, TRUE)"
+                )
+                    .bind(&stored)
+                    .bind(&timestamp)
+                    .bind(&hash)
+                    .bind(&blob)
+                    .execute(&self.pool)
+                .await?;
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+
+    /// Every history entry with its tags, oldest first
+    pub async fn export_entries(&self) -> Result<Vec<EntryRecord>> {
+        let mut records = sqlx::query_as::<_, EntryRecord>(
+            "SELECT id, content, timestamp, use_count, last_used, source_app, window_title, source_url, content_type, language, selection, blob, expires_at, origin_host
+FROM clipboard_history
+ORDER BY timestamp ASC, id ASC"
+        )
+            .fetch_all(&self.pool)
+        .await?;
+
+        let mut tags = self.get_entry_tags().await?;
+        for record in &mut records {
+            if let Some(content) = record.blob.as_deref().and_then(|blob| self.read_blob(blob)) {
+                record.content = content;
+            }
+            record.tags = tags.remove(&record.id).unwrap_or_default();
+        }
+        Ok(records)
+    }
+
+    /// Add exported entries back. An entry whose content (or a near-duplicate
+    /// of it) is already in the history is merged into it: it keeps the newer
+    /// timestamps and the larger use count, gains the tags and fills in
+    /// missing metadata. The default entry is left as it is. Like imported
+    /// entries, restored ones stay until the history is cleared. Returns how
+    /// many entries were new.
+    pub async fn restore_entries(&self, records: Vec<EntryRecord>) -> Result<usize> {
+        self.merge_records(records, true).await
+    }
+
+    /// Add entries shared by another machine, merged like in
+    /// `restore_entries`. Unlike restored entries they count towards the 50
+    /// newest entries kept. Returns how many entries were new.
+    pub async fn receive_entries(&self, records: Vec<EntryRecord>) -> Result<usize> {
+        let added = self.merge_records(records, false).await?;
+        self.cleanup_old_entries().await?;
+        Ok(added)
+    }
+
+    async fn merge_records(&self, records: Vec<EntryRecord>, imported: bool) -> Result<usize> {
+        let mut added = 0;
+
+        for record in records {
+            let hash = self.dedup_hash(&record.content);
+            let (stored, blob) = self.store_content(&record.content)?;
+            let existing = sqlx::query_as::<_, (i64,)>("SELECT id FROM clipboard_history WHERE content_hash = ?1")
+                .bind(&hash)
+                .fetch_optional(&self.pool)
+            .await?;
+
+            let id = match existing {
+                Some((id,)) => {
+                    sqlx::query(
+                        "UPDATE clipboard_history
+SET timestamp = MAX(timestamp, ?2),
+    last_used = CASE WHEN ?3 > COALESCE(last_used, '') THEN ?3 ELSE last_used END,
+    use_count = MAX(use_count, ?4),
+    source_app = COALESCE(source_app, ?5),
+    window_title = COALESCE(window_title, ?6),
+    source_url = COALESCE(source_url, ?7),
+    content_type = COALESCE(content_type, ?8),
+    language = COALESCE(language, ?9),
+    selection = CASE WHEN ?10 = 'clipboard' THEN ?10 ELSE selection END,
+    expires_at = CASE WHEN expires_at IS NULL THEN ?11 WHEN ?11 IS NULL THEN expires_at ELSE MIN(expires_at, ?11) END,
+    imported = imported OR ?12
+WHERE id = ?1"
+                    )
+                        .bind(id)
+                        .bind(sql_timestamp(record.timestamp))
+                        .bind(record.last_used.map(sql_timestamp))
+                        .bind(record.use_count)
+                        .bind(&record.source_app)
+                        .bind(&record.window_title)
+                        .bind(&record.source_url)
+                        .bind(&record.content_type)
+                        .bind(&record.language)
+                        .bind(record.selection)
+                        .bind(record.expires_at.map(sql_timestamp))
+                        .bind(imported)
+                        .execute(&self.pool)
+                    .await?;
+                    id
+                }
+                None => {
+                    added += 1;
+                    sqlx::query(
+                        "INSERT INTO clipboard_history (content, timestamp, last_used, use_count, source_app, window_title, source_url, content_type, language, selection, content_hash, blob, expires_at, imported, origin_host)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)"
+                    )
+                        .bind(&stored)
+                        .bind(sql_timestamp(record.timestamp))
+                        .bind(record.last_used.map(sql_timestamp))
+                        .bind(record.use_count.max(1))
+                        .bind(&record.source_app)
+                        .bind(&record.window_title)
+                        .bind(&record.source_url)
+                        .bind(&record.content_type)
+                        .bind(&record.language)
+                        .bind(record.selection)
+                        .bind(&hash)
+                        .bind(&blob)
+                        .bind(record.expires_at.map(sql_timestamp))
+                        .bind(imported)
+                        .bind(&record.origin_host)
+                        .execute(&self.pool)
+                    .await?
+                    .last_insert_rowid()
+                }
+            };
+
+            for tag in &record.tags {
+                self.tag_entry(id, tag).await?;
+            }
+        }
+
+        Ok(added)
     }
 
     async fn cleanup_old_entries(&self) -> Result<()> {
@@ -120,17 +770,108 @@ impl Database {
                 ORDER BY timestamp DESC
                 LIMIT 50
             )
+            AND id NOT IN (SELECT entry_id FROM paste_stack)
+            AND id NOT IN (SELECT entry_id FROM entry_tags)
+            AND NOT imported
             "#
         )
             .execute(&self.pool)
         .await?;
 
+        self.remove_unused_blobs().await
+    }
+
+    /// Remove every history entry except tagged ones and return how many were removed
+    pub async fn clear_history(&self) -> Result<u64> {
+        let removed = sqlx::query(
+            "DELETE FROM clipboard_history WHERE id NOT IN (SELECT entry_id FROM entry_tags)"
+        )
+            .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        self.remove_unused_blobs().await?;
+        Ok(removed)
+    }
+
+    /// The content to put in the `content` column and, for content over the
+    /// blob threshold, the file it was written to instead
+    fn store_content(&self, content: &str) -> Result<(String, Option<String>)> {
+        if content.len() <= self.blob_threshold {
+            return Ok((content.to_string(), None));
+        }
+
+        // Files are named by hash, so one that exists already holds this content
+        let hash = content_hash(content);
+        let path = self.blob_dir.join(&hash);
+        if path.exists() {
+            // Reused, so it is as new as a freshly written file to cleanups
+            std::fs::File::options().write(true).open(&path)?.set_modified(std::time::SystemTime::now())?;
+        } else {
+            std::fs::create_dir_all(&self.blob_dir)?;
+            let partial = path.with_extension("partial");
+            std::fs::write(&partial, content)?;
+            std::fs::rename(&partial, &path)?;
+        }
+
+        let mut end = BLOB_PREVIEW.min(content.len());
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        Ok((content[..end].to_string(), Some(hash)))
+    }
+
+    /// Read the content of entries stored in blobs. An entry whose file has
+    /// gone missing keeps its preview rather than failing the whole query.
+    fn load_blobs(&self, entries: &mut [ClipboardEntry]) {
+        for entry in entries {
+            if let Some(content) = entry.blob.as_deref().and_then(|blob| self.read_blob(blob)) {
+                entry.content = content;
+            }
+        }
+    }
+
+    fn read_blob(&self, blob: &str) -> Option<String> {
+        std::fs::read_to_string(self.blob_dir.join(blob)).ok()
+    }
+
+    /// Delete blob files that no entry refers to anymore. Files still being
+    /// written, or written too recently to belong to a committed entry, are
+    /// left for a later cleanup.
+    async fn remove_unused_blobs(&self) -> Result<()> {
+        let files = match std::fs::read_dir(&self.blob_dir) {
+            Ok(files) => files,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let used: HashSet<String> = sqlx::query_as::<_, (String,)>(
+            "SELECT blob FROM clipboard_history WHERE blob IS NOT NULL"
+        )
+            .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|(blob,)| blob)
+        .collect();
+
+        for file in files {
+            let file = file?;
+            let name = file.file_name().to_string_lossy().into_owned();
+            if used.contains(&name) || name.ends_with(".partial") {
+                continue;
+            }
+            let age = file.metadata()?.modified()?.elapsed().unwrap_or_default();
+            if age >= BLOB_GRACE_PERIOD {
+                std::fs::remove_file(file.path())?;
+            }
+        }
+
         Ok(())
     }
 
     pub async fn get_recent_entries(&self, limit: i64) -> Result<Vec<ClipboardEntry>> {
-        let entries = sqlx::query_as::<_, ClipboardEntry>(
-            "SELECT id, content, timestamp, is_default FROM clipboard_history
+        let mut entries = sqlx::query_as::<_, ClipboardEntry>(
+            "SELECT id, content, timestamp, is_default, source_app, window_title, source_url, content_type, language, selection, blob, expires_at, origin_host FROM clipboard_history
 ORDER BY timestamp DESC
 LIMIT ?1"
         )
@@ -138,18 +879,42 @@ LIMIT ?1"
             .fetch_all(&self.pool)
         .await?;
 
+        self.load_blobs(&mut entries);
+        Ok(entries)
+    }
+
+    /// Entries ranked by how often and how lately they were used
+    pub async fn get_frecent_entries(&self, limit: i64) -> Result<Vec<ClipboardEntry>> {
+        let mut entries = sqlx::query_as::<_, ClipboardEntry>(
+            "SELECT id, content, timestamp, is_default, source_app, window_title, source_url, content_type, language, selection, blob, expires_at, origin_host FROM clipboard_history
+ORDER BY use_count * CASE
+        WHEN julianday('now') - julianday(COALESCE(last_used, timestamp)) < 0.25 THEN 100
+        WHEN julianday('now') - julianday(COALESCE(last_used, timestamp)) < 1 THEN 70
+        WHEN julianday('now') - julianday(COALESCE(last_used, timestamp)) < 7 THEN 50
+        WHEN julianday('now') - julianday(COALESCE(last_used, timestamp)) < 30 THEN 30
+        ELSE 10
+    END DESC,
+    timestamp DESC
+LIMIT ?1"
+        )
+            .bind(limit)
+            .fetch_all(&self.pool)
+        .await?;
+
+        self.load_blobs(&mut entries);
         Ok(entries)
     }
 
     pub async fn get_default_entry(&self) -> Result<Option<ClipboardEntry>> {
-        let entry = sqlx::query_as::<_, ClipboardEntry>(
-            "SELECT id, content, timestamp, is_default FROM clipboard_history
+        let mut entry = sqlx::query_as::<_, ClipboardEntry>(
+            "SELECT id, content, timestamp, is_default, source_app, window_title, source_url, content_type, language, selection, blob, expires_at, origin_host FROM clipboard_history
 WHERE is_default = TRUE
 LIMIT 1"
         )
             .fetch_optional(&self.pool)
         .await?;
 
+        self.load_blobs(entry.as_mut_slice());
         Ok(entry)
     }
 
@@ -161,9 +926,253 @@ LIMIT 1"
             .execute(&mut *tx)
         .await?;
 
-        // Set new default
-        sqlx::query("UPDATE clipboard_history SET is_default = TRUE WHERE id = ?1")
+        // Set new default and count it as a use
+        sqlx::query(
+            "UPDATE clipboard_history
+SET is_default = TRUE, use_count = use_count + 1, last_used = CURRENT_TIMESTAMP
+WHERE id = ?1"
+        )
+            .bind(id)
+            .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Store how an entry was classified
+    pub async fn set_content_type(&self, id: i64, content_type: &str, language: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE clipboard_history SET content_type = ?1, language = ?2 WHERE id = ?3")
+            .bind(content_type)
+            .bind(language)
+            .bind(id)
+            .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Make an entry remove itself at `expires_at`, or keep it again with `None`
+    pub async fn set_expiry(&self, id: i64, expires_at: Option<DateTime<Utc>>) -> Result<()> {
+        let result = sqlx::query("UPDATE clipboard_history SET expires_at = ?1 WHERE id = ?2")
+            .bind(expires_at.map(sql_timestamp))
+            .bind(id)
+            .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("No clipboard entry with id {}", id));
+        }
+        Ok(())
+    }
+
+    /// Remove the entries whose time is up, tagged or not, and return them
+    pub async fn purge_expired(&self) -> Result<Vec<ClipboardEntry>> {
+        let now = sql_timestamp(Utc::now());
+        let mut tx = self.pool.begin().await?;
+
+        let expired = sqlx::query_as::<_, ClipboardEntry>(
+            "SELECT id, content, timestamp, is_default, source_app, window_title, source_url, content_type, language, selection, blob, expires_at, origin_host FROM clipboard_history
+WHERE expires_at <= ?1"
+        )
+            .bind(&now)
+            .fetch_all(&mut *tx)
+        .await?;
+        if expired.is_empty() {
+            return Ok(expired);
+        }
+
+        // Tags and paste stack slots go with them
+        sqlx::query("DELETE FROM clipboard_history WHERE expires_at <= ?1")
+            .bind(&now)
+            .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.remove_unused_blobs().await?;
+        Ok(expired)
+    }
+
+    pub async fn get_entry(&self, id: i64) -> Result<Option<ClipboardEntry>> {
+        let mut entry = sqlx::query_as::<_, ClipboardEntry>(
+            "SELECT id, content, timestamp, is_default, source_app, window_title, source_url, content_type, language, selection, blob, expires_at, origin_host FROM clipboard_history
+WHERE id = ?1"
+        )
             .bind(id)
+            .fetch_optional(&self.pool)
+        .await?;
+
+        self.load_blobs(entry.as_mut_slice());
+        Ok(entry)
+    }
+
+    /// Replace the content of an entry, keeping content unique like `add_entry` does.
+    ///
+    /// If another entry already holds the new content or a near-duplicate of it,
+    /// that entry takes the new content, is bumped and given the default flag if
+    /// the edited entry had it, and the edited entry is removed. Returns the id
+    /// of the entry that now holds the content.
+    pub async fn update_entry(&self, id: i64, content: String) -> Result<i64> {
+        let hash = self.dedup_hash(&content);
+        let (stored, blob) = self.store_content(&content)?;
+        let mut tx = self.pool.begin().await?;
+
+        let existing = sqlx::query_as::<_, (i64,)>(
+            "SELECT id FROM clipboard_history WHERE content_hash = ?1"
+        )
+            .bind(&hash)
+            .fetch_optional(&mut *tx)
+        .await?;
+
+        let surviving_id = match existing {
+            Some((other_id,)) if other_id != id => {
+                sqlx::query(
+                    "UPDATE clipboard_history
+SET content = ?1, blob = ?2, timestamp = CURRENT_TIMESTAMP, last_used = CURRENT_TIMESTAMP
+WHERE id = ?3"
+                )
+                    .bind(&stored)
+                    .bind(&blob)
+                    .bind(other_id)
+                    .execute(&mut *tx)
+                .await?;
+
+                fold_entry(&mut tx, id, other_id).await?;
+
+                other_id
+            }
+            _ => {
+                // The content type no longer applies to the edited text
+                let result = sqlx::query(
+                    "UPDATE clipboard_history
+SET content = ?1, content_hash = ?2, blob = ?3, timestamp = CURRENT_TIMESTAMP, content_type = NULL, language = NULL
+WHERE id = ?4"
+                )
+                    .bind(&stored)
+                    .bind(&hash)
+                    .bind(&blob)
+                    .bind(id)
+                    .execute(&mut *tx)
+                .await?;
+
+                if result.rows_affected() == 0 {
+                    return Err(anyhow::anyhow!("No clipboard entry with id {}", id));
+                }
+
+                id
+            }
+        };
+
+        tx.commit().await?;
+
+        self.remove_unused_blobs().await?;
+        Ok(surviving_id)
+    }
+
+    /// Append an entry to the end of the paste stack
+    pub async fn push_stack(&self, entry_id: i64) -> Result<()> {
+        sqlx::query("INSERT INTO paste_stack (entry_id) VALUES (?1)")
+            .bind(entry_id)
+            .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Entries on the paste stack, next to be pasted first
+    pub async fn get_stack_entries(&self) -> Result<Vec<ClipboardEntry>> {
+        let mut entries = sqlx::query_as::<_, ClipboardEntry>(
+            "SELECT h.id, h.content, h.timestamp, h.is_default, h.source_app, h.window_title, h.source_url, h.content_type, h.language, h.selection, h.blob, h.expires_at, h.origin_host FROM paste_stack s
+JOIN clipboard_history h ON h.id = s.entry_id
+ORDER BY s.position ASC"
+        )
+            .fetch_all(&self.pool)
+        .await?;
+
+        self.load_blobs(&mut entries);
+        Ok(entries)
+    }
+
+    /// The stack position and entry that should currently be on the clipboard
+    pub async fn get_stack_head(&self) -> Result<Option<(i64, ClipboardEntry)>> {
+        let head = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT position, entry_id FROM paste_stack ORDER BY position ASC LIMIT 1"
+        )
+            .fetch_optional(&self.pool)
+        .await?;
+
+        match head {
+            Some((position, entry_id)) => {
+                Ok(self.get_entry(entry_id).await?.map(|entry| (position, entry)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Remove the head of the paste stack and return the entry that was on it
+    pub async fn pop_stack(&self) -> Result<Option<ClipboardEntry>> {
+        let head = self.get_stack_head().await?;
+
+        if let Some((position, _)) = &head {
+            sqlx::query("DELETE FROM paste_stack WHERE position = ?1")
+                .bind(position)
+                .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(head.map(|(_, entry)| entry))
+    }
+
+    pub async fn clear_stack(&self) -> Result<()> {
+        sqlx::query("DELETE FROM paste_stack")
+            .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Attach a tag to an entry, creating the tag if needed.
+    ///
+    /// Tagged entries are kept when old history is cleaned up.
+    pub async fn tag_entry(&self, entry_id: i64, tag: &str) -> Result<()> {
+        let tag = normalize_tag(tag)?;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?1)")
+            .bind(tag)
+            .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT OR IGNORE INTO entry_tags (entry_id, tag_id)
+SELECT ?1, id FROM tags WHERE name = ?2"
+        )
+            .bind(entry_id)
+            .bind(tag)
+            .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Remove a tag from an entry, dropping the tag once nothing uses it
+    pub async fn untag_entry(&self, entry_id: i64, tag: &str) -> Result<()> {
+        let tag = normalize_tag(tag)?;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "DELETE FROM entry_tags
+WHERE entry_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)"
+        )
+            .bind(entry_id)
+            .bind(tag)
+            .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM entry_tags)")
             .execute(&mut *tx)
         .await?;
 
@@ -171,4 +1180,208 @@ LIMIT 1"
 
         Ok(())
     }
+
+    /// All entries with a tag, newest first, however old they are
+    pub async fn entries_with_tag(&self, tag: &str) -> Result<Vec<ClipboardEntry>> {
+        let mut entries = sqlx::query_as::<_, ClipboardEntry>(
+            "SELECT h.id, h.content, h.timestamp, h.is_default, h.source_app, h.window_title, h.source_url, h.content_type, h.language, h.selection, h.blob, h.expires_at, h.origin_host FROM clipboard_history h
+JOIN entry_tags et ON et.entry_id = h.id
+JOIN tags t ON t.id = et.tag_id
+WHERE t.name = ?1
+ORDER BY h.timestamp DESC"
+        )
+            .bind(normalize_tag(tag)?)
+            .fetch_all(&self.pool)
+        .await?;
+
+        self.load_blobs(&mut entries);
+        Ok(entries)
+    }
+
+    /// Tag names of every tagged entry, keyed by entry id
+    pub async fn get_entry_tags(&self) -> Result<HashMap<i64, Vec<String>>> {
+        let rows = sqlx::query_as::<_, (i64, String)>(
+            "SELECT et.entry_id, t.name FROM entry_tags et
+JOIN tags t ON t.id = et.tag_id
+ORDER BY t.name ASC"
+        )
+            .fetch_all(&self.pool)
+        .await?;
+
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        for (entry_id, name) in rows {
+            tags.entry(entry_id).or_default().push(name);
+        }
+        Ok(tags)
+    }
+
+    /// Snippets ordered by folder and name
+    pub async fn get_snippets(&self) -> Result<Vec<Snippet>> {
+        let snippets = sqlx::query_as::<_, Snippet>(
+            "SELECT id, name, folder, keyword, content, created_at, updated_at FROM snippets
+ORDER BY folder ASC, name ASC"
+        )
+            .fetch_all(&self.pool)
+        .await?;
+
+        Ok(snippets)
+    }
+
+    pub async fn find_snippet_by_keyword(&self, keyword: &str) -> Result<Option<Snippet>> {
+        let snippet = sqlx::query_as::<_, Snippet>(
+            "SELECT id, name, folder, keyword, content, created_at, updated_at FROM snippets
+WHERE keyword = ?1"
+        )
+            .bind(keyword)
+            .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(snippet)
+    }
+
+    /// Create a snippet, or replace the one with the same folder and name.
+    /// The keyword moves over from any other snippet using it. Returns the id
+    /// of the snippet.
+    pub async fn save_snippet(
+        &self,
+        folder: &str,
+        name: &str,
+        keyword: Option<&str>,
+        content: &str,
+    ) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let id = upsert_snippet(&mut tx, folder, name, keyword, content).await?;
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    /// Save snippets given as `(folder, name, keyword, content)` like
+    /// [`Database::save_snippet`], all of them or none
+    pub async fn save_snippets<'a>(
+        &self,
+        snippets: impl IntoIterator<Item = (&'a str, &'a str, Option<&'a str>, &'a str)>,
+    ) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let mut saved = 0;
+        for (folder, name, keyword, content) in snippets {
+            upsert_snippet(&mut tx, folder, name, keyword, content)
+                .await
+                .map_err(|e| anyhow::anyhow!("Could not save snippet {:?} in {:?}: {}", name, folder, e))?;
+            saved += 1;
+        }
+        tx.commit().await?;
+
+        Ok(saved)
+    }
+
+    pub async fn delete_snippet(&self, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM snippets WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Insert or update a snippet by folder and name, taking its keyword away
+/// from whichever other snippet had it
+async fn upsert_snippet(
+    conn: &mut SqliteConnection,
+    folder: &str,
+    name: &str,
+    keyword: Option<&str>,
+    content: &str,
+) -> Result<i64> {
+    let (folder, name) = (folder.trim(), name.trim());
+    let keyword = keyword.map(str::trim).filter(|keyword| !keyword.is_empty());
+
+    if let Some(keyword) = keyword {
+        sqlx::query("UPDATE snippets SET keyword = NULL WHERE keyword = ?1 AND NOT (folder = ?2 AND name = ?3)")
+            .bind(keyword)
+            .bind(folder)
+            .bind(name)
+            .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO snippets (folder, name, keyword, content) VALUES (?1, ?2, ?3, ?4)
+ON CONFLICT (folder, name) DO UPDATE SET
+    keyword = excluded.keyword,
+    content = excluded.content,
+    updated_at = CURRENT_TIMESTAMP"
+    )
+        .bind(folder)
+        .bind(name)
+        .bind(keyword)
+        .bind(content)
+        .execute(&mut *conn)
+    .await?;
+
+    // last_insert_rowid() isn't set when the upsert updated a row
+    let (id,) = sqlx::query_as::<_, (i64,)>("SELECT id FROM snippets WHERE folder = ?1 AND name = ?2")
+        .bind(folder)
+        .bind(name)
+        .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(id)
+}
+
+/// Merge the entry `from` into `into`: its uses, default flag, stacked pastes
+/// and tags move over and the entry is removed
+async fn fold_entry(conn: &mut SqliteConnection, from: i64, into: i64) -> Result<()> {
+    sqlx::query(
+        "UPDATE clipboard_history
+SET is_default = is_default OR (SELECT is_default FROM clipboard_history WHERE id = ?2),
+    use_count = use_count + (SELECT use_count FROM clipboard_history WHERE id = ?2)
+WHERE id = ?1"
+    )
+        .bind(into)
+        .bind(from)
+        .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("UPDATE paste_stack SET entry_id = ?1 WHERE entry_id = ?2")
+        .bind(into)
+        .bind(from)
+        .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "INSERT OR IGNORE INTO entry_tags (entry_id, tag_id)
+SELECT ?1, tag_id FROM entry_tags WHERE entry_id = ?2"
+    )
+        .bind(into)
+        .bind(from)
+        .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DELETE FROM clipboard_history WHERE id = ?1")
+        .bind(from)
+        .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// A timestamp formatted like CURRENT_TIMESTAMP, so the two sort together
+fn sql_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// SHA-256 of the content, in hex, which entries are deduplicated by
+pub fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Tags are matched without case and may be written with a leading '#'
+fn normalize_tag(tag: &str) -> Result<&str> {
+    let tag = tag.trim().trim_start_matches('#').trim();
+    if tag.is_empty() {
+        return Err(anyhow::anyhow!("Tag name is empty"));
+    }
+    Ok(tag)
 }
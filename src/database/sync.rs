@@ -0,0 +1,412 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteConnection;
+use std::collections::BTreeMap;
+
+use super::{content_hash, fold_entry, normalize_tag, sql_timestamp, Database, EntryRecord};
+
+/// One line of a machine's change log: the state of an entry after it was
+/// added or changed there, or its removal.
+///
+/// Entries are identified across machines by `uuid`. Changes are ordered by
+/// a Lamport clock, with the machine id breaking ties, and the latest change
+/// of an entry wins on every machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub uuid: String,
+    pub clock: i64,
+    pub machine: String,
+    /// The whole entry, or None when it was removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<EntryRecord>,
+}
+
+impl Change {
+    fn newer_than(&self, version: &(i64, String)) -> bool {
+        (self.clock, &self.machine) > (version.0, &version.1)
+    }
+}
+
+/// Local changes collected by `Database::collect_changes`, to be written to
+/// this machine's log and then passed to `Database::mark_sent`
+#[derive(Debug)]
+pub struct PendingChanges {
+    pub changes: Vec<Change>,
+    /// Entry id each change is about, with the fingerprint it was sent with
+    /// or None for removals
+    entries: Vec<(i64, Option<String>)>,
+    clock: i64,
+    /// `history_version` the changes were collected at
+    version: String,
+}
+
+impl Database {
+    /// Id this database writes its change log under, created on first use
+    pub async fn sync_machine_id(&self) -> Result<String> {
+        if let Some(id) = self.setting("sync_machine").await? {
+            return Ok(id);
+        }
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        sqlx::query("INSERT INTO settings (key, value) VALUES ('sync_machine', ?1)")
+            .bind(&id)
+            .execute(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    /// Collect the entries added, changed or removed since changes were last
+    /// marked sent by `mark_sent`, as changes made by `machine`.
+    ///
+    /// Nothing is recorded yet, so the changes can be written out without
+    /// holding on to the database, and changes that fail to be written are
+    /// collected again next time. The history is only gone through when it
+    /// changed since it was last marked sent.
+    pub async fn collect_changes(&self, machine: &str) -> Result<PendingChanges> {
+        let version = self.setting("history_version").await?.unwrap_or_default();
+        let mut pending = PendingChanges { changes: Vec::new(), entries: Vec::new(), clock: 0, version };
+        if self.setting("sync_sent_version").await?.as_ref() == Some(&pending.version) {
+            return Ok(pending);
+        }
+
+        let records = self.export_entries().await?;
+        let mut conn = self.pool.acquire().await?;
+        let mut clock = clock(&mut conn).await?;
+
+        // The smallest uuid of an entry is the one its changes are sent under
+        let rows = sqlx::query_as::<_, (String, i64, Option<String>)>(
+            "SELECT uuid, entry_id, fingerprint FROM sync_entries WHERE entry_id IS NOT NULL ORDER BY uuid DESC"
+        )
+            .fetch_all(&mut *conn)
+        .await?;
+        let mut known: BTreeMap<i64, (String, Option<String>)> = rows
+            .into_iter()
+            .map(|(uuid, entry_id, fingerprint)| (entry_id, (uuid, fingerprint)))
+            .collect();
+
+        for mut record in records {
+            record.blob = None;
+            let fingerprint = fingerprint(&record)?;
+            let uuid = match known.remove(&record.id) {
+                Some((_, Some(sent))) if sent == fingerprint => continue,
+                Some((uuid, _)) => uuid,
+                None => uuid::Uuid::new_v4().to_string(),
+            };
+
+            clock += 1;
+            pending.entries.push((record.id, Some(fingerprint)));
+            pending.changes.push(Change { uuid, clock, machine: machine.to_string(), entry: Some(record) });
+        }
+
+        // Whatever is left was removed here
+        for (entry_id, (uuid, _)) in known {
+            clock += 1;
+            pending.entries.push((entry_id, None));
+            pending.changes.push(Change { uuid, clock, machine: machine.to_string(), entry: None });
+        }
+
+        pending.clock = clock;
+        Ok(pending)
+    }
+
+    /// Record changes from `collect_changes` as sent, once they are written
+    /// to this machine's log
+    pub async fn mark_sent(&self, pending: &PendingChanges) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for ((entry_id, fingerprint), change) in pending.entries.iter().zip(&pending.changes) {
+            match fingerprint {
+                Some(fingerprint) => {
+                    sqlx::query("INSERT OR IGNORE INTO sync_entries (uuid, entry_id, clock, machine) VALUES (?1, ?2, 0, '')")
+                        .bind(&change.uuid)
+                        .bind(entry_id)
+                        .execute(&mut *tx)
+                    .await?;
+                    sqlx::query("UPDATE sync_entries SET clock = ?1, machine = ?2, fingerprint = ?3 WHERE entry_id = ?4")
+                        .bind(change.clock)
+                        .bind(&change.machine)
+                        .bind(fingerprint)
+                        .bind(entry_id)
+                        .execute(&mut *tx)
+                    .await?;
+                }
+                None => {
+                    sqlx::query(
+                        "UPDATE sync_entries SET entry_id = NULL, clock = ?1, machine = ?2, fingerprint = NULL WHERE entry_id = ?3"
+                    )
+                        .bind(change.clock)
+                        .bind(&change.machine)
+                        .bind(entry_id)
+                        .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+
+        let clock = clock(&mut tx).await?.max(pending.clock);
+        set_clock(&mut tx, clock).await?;
+        sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES ('sync_sent_version', ?1)")
+            .bind(&pending.version)
+            .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Merge changes made on other machines. Changes older than what is known
+    /// of an entry are ignored, so applying the same change twice is harmless.
+    ///
+    /// An entry with a new uuid whose content is already in the history is
+    /// merged into the existing entry, which is then known under both uuids.
+    /// Returns how many changes were applied.
+    pub async fn apply_changes(&self, changes: Vec<Change>) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let mut clock = clock(&mut tx).await?;
+        let mut applied = 0;
+
+        for change in changes {
+            clock = clock.max(change.clock);
+
+            let row = sqlx::query_as::<_, (Option<i64>, i64, String)>(
+                "SELECT entry_id, clock, machine FROM sync_entries WHERE uuid = ?1"
+            )
+                .bind(&change.uuid)
+                .fetch_optional(&mut *tx)
+            .await?;
+
+            let current = match &row {
+                Some((Some(entry_id), ..)) => entry_version(&mut tx, *entry_id).await?,
+                Some((None, clock, machine)) => Some((*clock, machine.clone())),
+                None => None,
+            };
+            if current.as_ref().is_some_and(|current| !change.newer_than(current)) {
+                continue;
+            }
+
+            let entry_id = row.and_then(|(entry_id, ..)| entry_id);
+            let entry_id = match (&change.entry, entry_id) {
+                (Some(record), entry_id) => Some(self.write_record(&mut tx, entry_id, record, &change).await?),
+                (None, Some(entry_id)) => {
+                    sqlx::query(
+                        "UPDATE sync_entries SET entry_id = NULL, clock = ?1, machine = ?2, fingerprint = NULL WHERE entry_id = ?3"
+                    )
+                        .bind(change.clock)
+                        .bind(&change.machine)
+                        .bind(entry_id)
+                        .execute(&mut *tx)
+                    .await?;
+                    sqlx::query("DELETE FROM clipboard_history WHERE id = ?1")
+                        .bind(entry_id)
+                        .execute(&mut *tx)
+                    .await?;
+                    None
+                }
+                (None, None) => None,
+            };
+
+            let fingerprint = change.entry.as_ref().map(fingerprint).transpose()?;
+            sqlx::query(
+                "INSERT INTO sync_entries (uuid, entry_id, clock, machine, fingerprint) VALUES (?1, ?2, ?3, ?4, ?5)
+ON CONFLICT (uuid) DO UPDATE SET
+    entry_id = excluded.entry_id,
+    clock = excluded.clock,
+    machine = excluded.machine,
+    fingerprint = excluded.fingerprint"
+            )
+                .bind(&change.uuid)
+                .bind(entry_id)
+                .bind(change.clock)
+                .bind(&change.machine)
+                .bind(&fingerprint)
+                .execute(&mut *tx)
+            .await?;
+
+            // The entry now looks like this under its other uuids too, so it
+            // isn't sent back as a local change
+            if let Some(entry_id) = entry_id {
+                sqlx::query("UPDATE sync_entries SET fingerprint = ?1 WHERE entry_id = ?2")
+                    .bind(&fingerprint)
+                    .bind(entry_id)
+                    .execute(&mut *tx)
+                .await?;
+            }
+            applied += 1;
+        }
+
+        sqlx::query("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM entry_tags)")
+            .execute(&mut *tx)
+        .await?;
+        set_clock(&mut tx, clock).await?;
+        tx.commit().await?;
+
+        self.cleanup_old_entries().await?;
+        Ok(applied)
+    }
+
+    /// How far the change log of `machine` has been read, in bytes
+    pub async fn sync_log_offset(&self, machine: &str) -> Result<u64> {
+        let offset = sqlx::query_as::<_, (i64,)>("SELECT offset FROM sync_logs WHERE machine = ?1")
+            .bind(machine)
+            .fetch_optional(&self.pool)
+        .await?;
+        Ok(offset.map_or(0, |(offset,)| offset as u64))
+    }
+
+    pub async fn set_sync_log_offset(&self, machine: &str, offset: u64) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO sync_logs (machine, offset) VALUES (?1, ?2)")
+            .bind(machine)
+            .bind(offset as i64)
+            .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn setting(&self, key: &str) -> Result<Option<String>> {
+        let value = sqlx::query_as::<_, (String,)>("SELECT value FROM settings WHERE key = ?1")
+            .bind(key)
+            .fetch_optional(&self.pool)
+        .await?;
+        Ok(value.map(|(value,)| value))
+    }
+
+    /// Give the entry `entry_id` (a new one when None) the state of a remote
+    /// change and return its id, which differs when another entry already
+    /// held the content and the two were merged
+    async fn write_record(
+        &self,
+        conn: &mut SqliteConnection,
+        entry_id: Option<i64>,
+        record: &EntryRecord,
+        change: &Change,
+    ) -> Result<i64> {
+        let hash = self.dedup_hash(&record.content);
+        let (stored, blob) = self.store_content(&record.content)?;
+        let existing = sqlx::query_as::<_, (i64,)>("SELECT id FROM clipboard_history WHERE content_hash = ?1")
+            .bind(&hash)
+            .fetch_optional(&mut *conn)
+        .await?;
+
+        let id = match (existing, entry_id) {
+            (Some((existing,)), Some(entry_id)) if existing != entry_id => {
+                fold_entry(conn, entry_id, existing).await?;
+                sqlx::query("UPDATE sync_entries SET entry_id = ?1 WHERE entry_id = ?2")
+                    .bind(existing)
+                    .bind(entry_id)
+                    .execute(&mut *conn)
+                .await?;
+                existing
+            }
+            (Some((existing,)), None) => {
+                // The same content copied on two machines: the local entry
+                // keeps its own state unless the remote change is newer
+                if let Some(version) = entry_version(conn, existing).await? {
+                    if !change.newer_than(&version) {
+                        return Ok(existing);
+                    }
+                }
+                existing
+            }
+            (_, Some(entry_id)) => entry_id,
+            (None, None) => {
+                sqlx::query("INSERT INTO clipboard_history (content, content_hash) VALUES ('', ?1)")
+                    .bind(&hash)
+                    .execute(&mut *conn)
+                .await?
+                .last_insert_rowid()
+            }
+        };
+
+        sqlx::query(
+            "UPDATE clipboard_history
+SET content = ?2,
+    content_hash = ?3,
+    blob = ?4,
+    timestamp = ?5,
+    last_used = ?6,
+    use_count = ?7,
+    source_app = ?8,
+    window_title = ?9,
+    source_url = ?10,
+    content_type = ?11,
+    language = ?12,
+    selection = ?13,
+    expires_at = ?14,
+    origin_host = ?15
+WHERE id = ?1"
+        )
+            .bind(id)
+            .bind(&stored)
+            .bind(&hash)
+            .bind(&blob)
+            .bind(sql_timestamp(record.timestamp))
+            .bind(record.last_used.map(sql_timestamp))
+            .bind(record.use_count.max(1))
+            .bind(&record.source_app)
+            .bind(&record.window_title)
+            .bind(&record.source_url)
+            .bind(&record.content_type)
+            .bind(&record.language)
+            .bind(record.selection)
+            .bind(record.expires_at.map(sql_timestamp))
+            .bind(&record.origin_host)
+            .execute(&mut *conn)
+        .await?;
+
+        sqlx::query("DELETE FROM entry_tags WHERE entry_id = ?1")
+            .bind(id)
+            .execute(&mut *conn)
+        .await?;
+        for tag in &record.tags {
+            let tag = normalize_tag(tag)?;
+            sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?1)")
+                .bind(tag)
+                .execute(&mut *conn)
+            .await?;
+            sqlx::query(
+                "INSERT OR IGNORE INTO entry_tags (entry_id, tag_id)
+SELECT ?1, id FROM tags WHERE name = ?2"
+            )
+                .bind(id)
+                .bind(tag)
+                .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(id)
+    }
+}
+
+/// The latest change known of an entry, under any of its uuids
+async fn entry_version(conn: &mut SqliteConnection, entry_id: i64) -> Result<Option<(i64, String)>> {
+    let version = sqlx::query_as::<_, (i64, String)>(
+        "SELECT clock, machine FROM sync_entries WHERE entry_id = ?1 ORDER BY clock DESC, machine DESC LIMIT 1"
+    )
+        .bind(entry_id)
+        .fetch_optional(&mut *conn)
+    .await?;
+    Ok(version)
+}
+
+async fn clock(conn: &mut SqliteConnection) -> Result<i64> {
+    let clock = sqlx::query_as::<_, (String,)>("SELECT value FROM settings WHERE key = 'sync_clock'")
+        .fetch_optional(&mut *conn)
+    .await?;
+    Ok(clock.and_then(|(clock,)| clock.parse().ok()).unwrap_or(0))
+}
+
+async fn set_clock(conn: &mut SqliteConnection, clock: i64) -> Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES ('sync_clock', ?1)")
+        .bind(clock.to_string())
+        .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Hash of everything a change carries, to notice entries that changed
+fn fingerprint(record: &EntryRecord) -> Result<String> {
+    let mut record = record.clone();
+    record.tags = record.tags.iter().map(|tag| tag.to_lowercase()).collect();
+    record.tags.sort();
+    record.tags.dedup();
+    Ok(content_hash(&serde_json::to_string(&record)?))
+}
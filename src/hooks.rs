@@ -0,0 +1,261 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+use crate::config::{HookCommand, HooksConfig};
+use crate::database::ClipboardEntry;
+
+/// Hook output beyond this is ignored, so a runaway script can't fill memory
+const MAX_OUTPUT: u64 = 1024 * 1024;
+
+/// Environment variables passed on to hooks; everything else is cleared
+const KEPT_ENV: &[&str] = &[
+    "PATH", "HOME", "USER", "LANG", "LC_ALL", "XDG_RUNTIME_DIR", "WAYLAND_DISPLAY", "DISPLAY",
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// New content was copied and is about to be stored
+    Capture,
+    /// An entry was picked in the popup
+    Select,
+    /// The history was cleared
+    Clear,
+}
+
+impl HookEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::Capture => "capture",
+            HookEvent::Select => "select",
+            HookEvent::Clear => "clear",
+        }
+    }
+}
+
+/// JSON written to a hook's stdin
+#[derive(Serialize)]
+struct HookInput<'a> {
+    event: &'static str,
+    entry: Option<&'a ClipboardEntry>,
+}
+
+/// JSON a capture hook may print on stdout. Printing nothing keeps the content.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct HookReply {
+    /// Don't store the content at all
+    veto: bool,
+    /// Store this instead of the copied content
+    content: Option<String>,
+}
+
+/// The user's hook commands
+pub struct Hooks {
+    config: HooksConfig,
+}
+
+impl Hooks {
+    pub fn new(config: HooksConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run the capture hooks in order, each seeing the previous one's rewrite.
+    ///
+    /// Returns the content to store, or None if a hook vetoed it. A hook that
+    /// fails or times out is logged and skipped.
+    pub async fn on_capture(&self, mut entry: ClipboardEntry) -> Option<String> {
+        for hook in &self.config.on_capture {
+            let output = match self.run(hook, HookEvent::Capture, Some(&entry)).await {
+                Ok(output) => output,
+                Err(e) => {
+                    warn!("Capture hook {:?} failed: {:#}", hook.command, e);
+                    continue;
+                }
+            };
+            if output.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<HookReply>(&output) {
+                Ok(reply) if reply.veto => {
+                    info!("Capture hook {:?} vetoed the copied content", hook.command);
+                    return None;
+                }
+                Ok(reply) => {
+                    if let Some(content) = reply.content {
+                        debug!("Capture hook {:?} rewrote the copied content", hook.command);
+                        entry.content = content;
+                    }
+                }
+                Err(e) => warn!("Capture hook {:?} printed invalid JSON: {}", hook.command, e),
+            }
+        }
+
+        Some(entry.content)
+    }
+
+    pub async fn on_select(&self, entry: &ClipboardEntry) {
+        self.notify(&self.config.on_select, HookEvent::Select, Some(entry)).await;
+    }
+
+    pub async fn on_clear(&self) {
+        self.notify(&self.config.on_clear, HookEvent::Clear, None).await;
+    }
+
+    /// Run hooks whose output doesn't matter
+    async fn notify(&self, hooks: &[HookCommand], event: HookEvent, entry: Option<&ClipboardEntry>) {
+        for hook in hooks {
+            if let Err(e) = self.run(hook, event, entry).await {
+                warn!("{} hook {:?} failed: {:#}", event.name(), hook.command, e);
+            }
+        }
+    }
+
+    /// Run one hook with the event on stdin and return what it printed.
+    ///
+    /// Hooks run without a shell, with a cleared environment (see `KEPT_ENV`),
+    /// in their own process group and the temporary directory, and are killed
+    /// when they exceed their time limit.
+    async fn run(&self, hook: &HookCommand, event: HookEvent, entry: Option<&ClipboardEntry>) -> Result<String> {
+        let (program, args) = hook.command.split_first().context("Hook has an empty command")?;
+        let input = serde_json::to_vec(&HookInput { event: event.name(), entry })?;
+
+        let mut command = Command::new(expand_home(program));
+        command
+            .args(args)
+            .env_clear()
+            .envs(KEPT_ENV.iter().filter_map(|name| std::env::var_os(name).map(|value| (name, value))))
+            .env("CLIPPOO_EVENT", event.name())
+            .current_dir(std::env::temp_dir())
+            .process_group(0)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = command.spawn().with_context(|| format!("Could not start {}", program))?;
+        let mut stdin = child.stdin.take().context("No stdin")?;
+        let mut stdout = child.stdout.take().context("No stdout")?.take(MAX_OUTPUT);
+        let mut stderr = child.stderr.take().context("No stderr")?.take(MAX_OUTPUT);
+
+        let pid = child.id();
+        let limit = Duration::from_millis(hook.timeout_ms.unwrap_or(self.config.timeout_ms));
+        let run = async {
+            // Hooks that don't read their input close stdin early, which is fine
+            let _ = stdin.write_all(&input).await;
+            drop(stdin);
+
+            let mut output = String::new();
+            let mut errors = String::new();
+            let (read_out, read_err, status) = tokio::join!(
+                stdout.read_to_string(&mut output),
+                stderr.read_to_string(&mut errors),
+                child.wait()
+            );
+            read_out?;
+            read_err?;
+            anyhow::Ok((status?, output, errors))
+        };
+
+        let (status, output, errors) = match timeout(limit, run).await {
+            Ok(result) => result?,
+            Err(_) => {
+                // kill_on_drop only reaches the hook itself, not what it started
+                if let Some(pid) = pid {
+                    kill_process_group(pid).await;
+                }
+                anyhow::bail!("timed out after {} ms", limit.as_millis());
+            }
+        };
+
+        if !errors.trim().is_empty() {
+            debug!("Hook {:?} stderr: {}", hook.command, errors.trim());
+        }
+        if !status.success() {
+            anyhow::bail!("exited with {}", status);
+        }
+        Ok(output)
+    }
+}
+
+async fn kill_process_group(pgid: u32) {
+    let _ = Command::new("kill")
+        .args(["-KILL", "--", &format!("-{}", pgid)])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await;
+}
+
+fn expand_home(program: &str) -> String {
+    match (program.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => program.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(script: &str) -> HookCommand {
+        HookCommand { command: vec!["sh".into(), "-c".into(), script.into()], timeout_ms: None }
+    }
+
+    fn entry(content: &str) -> ClipboardEntry {
+        ClipboardEntry {
+            id: 0,
+            content: content.to_string(),
+            timestamp: chrono::Utc::now(),
+            is_default: true,
+            source_app: Some("foot".into()),
+            window_title: None,
+            source_url: None,
+            content_type: None,
+            language: None,
+            selection: Default::default(),
+            blob: None,
+            expires_at: None,
+            origin_host: None,
+        }
+    }
+
+    fn hooks(on_capture: Vec<HookCommand>) -> Hooks {
+        Hooks::new(HooksConfig { on_capture, timeout_ms: 500, ..HooksConfig::default() })
+    }
+
+    #[tokio::test]
+    async fn test_capture_hooks_rewrite_and_veto() {
+        let rewrite = hooks(vec![
+            hook(r#"echo '{"content": "redacted"}'"#),
+            // Sees the previous rewrite
+            hook(r#"grep -q '"content":"redacted".*"source_app":"foot"' && echo "{\"content\": \"$CLIPPOO_EVENT\"}""#),
+            // Prints nothing and so keeps it
+            hook("cat > /dev/null"),
+        ]);
+        assert_eq!(rewrite.on_capture(entry("secret")).await.as_deref(), Some("capture"));
+
+        let veto = hooks(vec![hook(r#"echo '{"veto": true}'"#), hook("exit 1")]);
+        assert_eq!(veto.on_capture(entry("secret")).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_failing_hooks_keep_content() {
+        let failing = hooks(vec![
+            hook("echo '{\"content\": \"x\"}'; exit 3"),
+            hook("sleep 5"),
+            hook("echo not json"),
+            HookCommand { command: Vec::new(), timeout_ms: None },
+        ]);
+
+        let started = std::time::Instant::now();
+        assert_eq!(failing.on_capture(entry("kept")).await.as_deref(), Some("kept"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
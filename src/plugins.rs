@@ -0,0 +1,443 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, Map, AST};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::actions::{ActionOutcome, ActionRegistry};
+use crate::classify::ContentType;
+use crate::config::PluginsConfig;
+use crate::database::ClipboardEntry;
+use crate::transform::TransformRegistry;
+
+/// Read access to the history for plugins: the `limit` most recent entries
+pub type HistoryFn = Arc<dyn Fn(i64) -> Vec<ClipboardEntry> + Send + Sync>;
+
+/// What a plugin registered a function as
+#[derive(Debug, Clone)]
+enum Kind {
+    Filter,
+    Classifier,
+    Transform { name: String, label: String },
+    Action { content_type: ContentType, name: String, label: String },
+}
+
+struct Registration {
+    plugin: usize,
+    kind: Kind,
+    func: FnPtr,
+}
+
+struct Plugin {
+    name: String,
+    ast: AST,
+}
+
+struct Host {
+    engine: Engine,
+    plugins: Vec<Plugin>,
+    registrations: Vec<Registration>,
+    /// When the running call has used up its time budget
+    deadline: Arc<Mutex<Instant>>,
+    budget: Duration,
+}
+
+/// Rhai plugins loaded from `~/.config/clippoo/plugins/*.rhai`.
+///
+/// A plugin is a script that calls `register_filter`, `register_classifier`,
+/// `register_transform` and `register_action` with functions taking an entry
+/// map (or the entry text, for transforms and actions). Every call runs with
+/// the configured time budget and size limits, so a broken plugin fails
+/// instead of stalling the watcher.
+#[derive(Clone)]
+pub struct Plugins {
+    host: Arc<Host>,
+}
+
+impl Plugins {
+    /// Load the plugins from the user's plugin directory, if enabled
+    pub fn load(config: &PluginsConfig, history: Option<HistoryFn>) -> Self {
+        let dir = dirs::config_dir().unwrap_or_default().join("clippoo").join("plugins");
+        Self::load_from(&dir, config, history)
+    }
+
+    /// Load every `*.rhai` file in `dir`, in name order. Plugins that fail to
+    /// compile or run are logged and skipped, and a missing directory means no plugins.
+    pub fn load_from(dir: &Path, config: &PluginsConfig, history: Option<HistoryFn>) -> Self {
+        let deadline = Arc::new(Mutex::new(Instant::now()));
+        let pending: Arc<Mutex<Vec<(Kind, FnPtr)>>> = Arc::new(Mutex::new(Vec::new()));
+        let engine = new_engine(config, deadline.clone(), pending.clone(), history);
+
+        let mut host = Host {
+            engine,
+            plugins: Vec::new(),
+            registrations: Vec::new(),
+            deadline,
+            budget: Duration::from_millis(config.time_budget_ms),
+        };
+
+        let mut files: Vec<_> = match std::fs::read_dir(dir) {
+            Ok(entries) if config.enabled => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+                .collect(),
+            _ => Vec::new(),
+        };
+        files.sort();
+
+        for path in files {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            pending.lock().unwrap().clear();
+
+            match host.run_plugin(&path) {
+                Ok(ast) => {
+                    let plugin = host.plugins.len();
+                    host.registrations.extend(
+                        pending.lock().unwrap().drain(..).map(|(kind, func)| Registration { plugin, kind, func }),
+                    );
+                    host.plugins.push(Plugin { name: name.clone(), ast });
+                    info!("Loaded plugin {}", name);
+                }
+                Err(e) => warn!("Skipping plugin {}: {:#}", path.display(), e),
+            }
+        }
+
+        Self { host: Arc::new(host) }
+    }
+
+    /// Run the filters in order, each seeing the previous one's rewrite.
+    ///
+    /// A filter returns `false` to drop the content, a string to replace it,
+    /// or anything else to keep it. Returns the content to store, or None if
+    /// it was dropped.
+    pub fn filter(&self, mut entry: ClipboardEntry) -> Option<String> {
+        for registration in self.registrations(|kind| matches!(kind, Kind::Filter)) {
+            match self.host.call(registration, (Dynamic::from_map(entry_map(&entry)),)) {
+                Ok(result) if result.as_bool() == Ok(false) => {
+                    info!("Plugin {} dropped the copied content", self.host.plugin_name(registration));
+                    return None;
+                }
+                Ok(result) if result.is_string() => {
+                    debug!("Plugin {} rewrote the copied content", self.host.plugin_name(registration));
+                    entry.content = result.into_string().unwrap_or_default();
+                }
+                Ok(_) => {}
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+
+        Some(entry.content)
+    }
+
+    /// The first plugin classification of an entry, if any plugin has one.
+    ///
+    /// A classifier returns a content type name, `#{ type: "code", language: "hcl" }`
+    /// or `()` to leave the entry to the built-in classification.
+    pub fn classify(&self, entry: &ClipboardEntry) -> Option<(ContentType, Option<String>)> {
+        for registration in self.registrations(|kind| matches!(kind, Kind::Classifier)) {
+            let result = match self.host.call(registration, (Dynamic::from_map(entry_map(entry)),)) {
+                Ok(result) => result,
+                Err(e) => {
+                    warn!("{:#}", e);
+                    continue;
+                }
+            };
+
+            let (name, language) = match result.clone().try_cast::<Map>() {
+                Some(map) => (map.get("type").cloned(), map.get("language").cloned()),
+                None => (Some(result), None),
+            };
+            let Some(name) = name.filter(|name| !name.is_unit()) else {
+                continue;
+            };
+
+            let name = name.into_string().unwrap_or_default();
+            match ContentType::from_name(&name) {
+                Some(content_type) => {
+                    let language = language.and_then(|language| language.into_string().ok());
+                    return Some((content_type, language));
+                }
+                None => warn!("Plugin {} returned unknown content type '{}'", self.host.plugin_name(registration), name),
+            }
+        }
+
+        None
+    }
+
+    /// Offer the plugins' transforms in "paste as…"
+    pub fn register_transforms(&self, registry: &mut TransformRegistry) {
+        for (index, registration) in self.host.registrations.iter().enumerate() {
+            if let Kind::Transform { name, label } = &registration.kind {
+                let host = self.host.clone();
+                registry.register(name, label, move |input| {
+                    let result = host.call(&host.registrations[index], (input.to_string(),))?;
+                    result.into_string().map_err(|t| anyhow::anyhow!("Transform returned {} instead of a string", t))
+                });
+            }
+        }
+    }
+
+    /// Offer the plugins' actions in the quick actions menu
+    pub fn register_actions(&self, registry: &mut ActionRegistry) {
+        for (index, registration) in self.host.registrations.iter().enumerate() {
+            if let Kind::Action { content_type, name, label } = &registration.kind {
+                let host = self.host.clone();
+                registry.register(*content_type, name, label, move |input| {
+                    action_outcome(host.call(&host.registrations[index], (input.to_string(),))?)
+                });
+            }
+        }
+    }
+
+    /// The loaded plugins with what each of them registered
+    pub fn describe(&self) -> Vec<(String, Vec<String>)> {
+        self.host
+            .plugins
+            .iter()
+            .enumerate()
+            .map(|(plugin, Plugin { name, .. })| {
+                let registered = self
+                    .host
+                    .registrations
+                    .iter()
+                    .filter(|registration| registration.plugin == plugin)
+                    .map(|registration| match &registration.kind {
+                        Kind::Filter => "filter".to_string(),
+                        Kind::Classifier => "classifier".to_string(),
+                        Kind::Transform { name, .. } => format!("transform {}", name),
+                        Kind::Action { content_type, name, .. } => format!("action {} ({})", name, content_type.name()),
+                    })
+                    .collect();
+                (name.clone(), registered)
+            })
+            .collect()
+    }
+
+    fn registrations(&self, kind: impl Fn(&Kind) -> bool) -> impl Iterator<Item = &Registration> {
+        self.host.registrations.iter().filter(move |registration| kind(&registration.kind))
+    }
+}
+
+impl Host {
+    /// Compile a plugin and run its top level, which does the registering
+    fn run_plugin(&self, path: &Path) -> Result<AST> {
+        let source = std::fs::read_to_string(path).context("Could not read plugin")?;
+        let ast = self.engine.compile(&source).map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        self.start_budget();
+        self.engine.run_ast(&ast).map_err(|e| self.error(*e))?;
+        Ok(ast)
+    }
+
+    fn call(&self, registration: &Registration, args: impl rhai::FuncArgs) -> Result<Dynamic> {
+        let plugin = &self.plugins[registration.plugin];
+
+        self.start_budget();
+        registration
+            .func
+            .call::<Dynamic>(&self.engine, &plugin.ast, args)
+            .map_err(|e| anyhow::anyhow!("Plugin {} failed: {}", plugin.name, self.error(*e)))
+    }
+
+    /// Errors of the script, with running out of time spelled out
+    fn error(&self, error: EvalAltResult) -> anyhow::Error {
+        match error {
+            EvalAltResult::ErrorTerminated(_, position) => {
+                anyhow::anyhow!("exceeded its time budget of {} ms ({})", self.budget.as_millis(), position)
+            }
+            error => anyhow::anyhow!("{}", error),
+        }
+    }
+
+    fn start_budget(&self) {
+        *self.deadline.lock().unwrap() = Instant::now() + self.budget;
+    }
+
+    fn plugin_name(&self, registration: &Registration) -> &str {
+        &self.plugins[registration.plugin].name
+    }
+}
+
+/// The engine with the plugin API and the budgets applied
+fn new_engine(
+    config: &PluginsConfig,
+    deadline: Arc<Mutex<Instant>>,
+    pending: Arc<Mutex<Vec<(Kind, FnPtr)>>>,
+    history: Option<HistoryFn>,
+) -> Engine {
+    let mut engine = Engine::new();
+
+    engine
+        .set_max_string_size(config.max_string_size)
+        .set_max_array_size(config.max_collection_size)
+        .set_max_map_size(config.max_collection_size)
+        .set_max_call_levels(64);
+    engine.on_progress(move |_| {
+        (Instant::now() > *deadline.lock().unwrap()).then(|| "time budget exceeded".into())
+    });
+    engine.on_print(|text| info!("plugin: {}", text));
+    engine.on_debug(|text, _, position| debug!("plugin ({}): {}", position, text));
+
+    let register = move |kind: Kind, func: FnPtr| pending.lock().unwrap().push((kind, func));
+    let filter = register.clone();
+    engine.register_fn("register_filter", move |func: FnPtr| filter(Kind::Filter, func));
+    let classifier = register.clone();
+    engine.register_fn("register_classifier", move |func: FnPtr| classifier(Kind::Classifier, func));
+    let transform = register.clone();
+    engine.register_fn("register_transform", move |name: &str, label: &str, func: FnPtr| {
+        transform(Kind::Transform { name: name.to_string(), label: label.to_string() }, func)
+    });
+    engine.register_fn(
+        "register_action",
+        move |content_type: &str, name: &str, label: &str, func: FnPtr| -> Result<(), Box<EvalAltResult>> {
+            let content_type = ContentType::from_name(content_type)
+                .ok_or_else(|| format!("Unknown content type '{}'", content_type))?;
+            register(Kind::Action { content_type, name: name.to_string(), label: label.to_string() }, func);
+            Ok(())
+        },
+    );
+
+    engine.register_fn("history", move |limit: i64| -> Array {
+        match &history {
+            Some(history) => history(limit).iter().map(|entry| Dynamic::from_map(entry_map(entry))).collect(),
+            None => Array::new(),
+        }
+    });
+
+    engine
+}
+
+/// How plugins see an entry
+fn entry_map(entry: &ClipboardEntry) -> Map {
+    let optional = |value: &Option<String>| value.clone().map_or(Dynamic::UNIT, Dynamic::from);
+
+    let mut map = Map::new();
+    map.insert("id".into(), Dynamic::from(entry.id));
+    map.insert("content".into(), Dynamic::from(entry.content.clone()));
+    map.insert("timestamp".into(), Dynamic::from(entry.timestamp.to_rfc3339()));
+    map.insert("is_default".into(), Dynamic::from(entry.is_default));
+    map.insert("source_app".into(), optional(&entry.source_app));
+    map.insert("window_title".into(), optional(&entry.window_title));
+    map.insert("source_url".into(), optional(&entry.source_url));
+    map.insert("content_type".into(), optional(&entry.content_type));
+    map.insert("language".into(), optional(&entry.language));
+    map.insert("selection".into(), Dynamic::from(entry.selection.name().to_string()));
+    map
+}
+
+/// A string is pasted; `#{ open: uri }`, `#{ reveal: uri }` and `#{ paste: text }` say what to do
+fn action_outcome(result: Dynamic) -> Result<ActionOutcome> {
+    if result.is_string() {
+        return Ok(ActionOutcome::Paste(result.into_string().unwrap_or_default()));
+    }
+
+    let map = result.try_cast::<Map>().context("Action returned nothing to do")?;
+    let value = |key: &str| map.get(key).and_then(|value| value.clone().into_string().ok());
+
+    if let Some(uri) = value("open") {
+        Ok(ActionOutcome::Open(uri))
+    } else if let Some(uri) = value("reveal") {
+        Ok(ActionOutcome::Reveal(uri))
+    } else if let Some(text) = value("paste") {
+        Ok(ActionOutcome::Paste(text))
+    } else {
+        anyhow::bail!("Action returned none of open, reveal or paste")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(content: &str) -> ClipboardEntry {
+        ClipboardEntry {
+            id: 0,
+            content: content.to_string(),
+            timestamp: chrono::Utc::now(),
+            is_default: true,
+            source_app: Some("foot".into()),
+            window_title: None,
+            source_url: None,
+            content_type: None,
+            language: None,
+            selection: Default::default(),
+            blob: None,
+            expires_at: None,
+            origin_host: None,
+        }
+    }
+
+    fn load(plugins: &[(&str, &str)]) -> Plugins {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, source) in plugins {
+            std::fs::write(dir.path().join(format!("{}.rhai", name)), source).unwrap();
+        }
+        let history: HistoryFn = Arc::new(|_| vec![entry("older copy")]);
+        let config = PluginsConfig { time_budget_ms: 200, ..PluginsConfig::default() };
+        Plugins::load_from(dir.path(), &config, Some(history))
+    }
+
+    #[test]
+    fn test_filters_and_classifiers() {
+        let plugins = load(&[
+            ("a_redact", r#"register_filter(|entry| { let text = entry.content; text.replace("hunter2", "*******"); text });"#),
+            ("b_drop", r#"register_filter(|entry| entry.source_app != "keepassxc" && entry.content.len() > 1);"#),
+            (
+                "c_tickets",
+                r#"register_classifier(|entry| if entry.content.starts_with("resource ") { #{ type: "code", language: "hcl" } });
+                   register_classifier(|entry| if entry.content == history(1)[0].content { "text" });"#,
+            ),
+        ]);
+
+        assert_eq!(plugins.filter(entry("pw hunter2")).as_deref(), Some("pw *******"));
+        assert_eq!(plugins.filter(entry("x")), None);
+        let mut secret = entry("secret");
+        secret.source_app = Some("keepassxc".into());
+        assert_eq!(plugins.filter(secret), None);
+
+        assert_eq!(
+            plugins.classify(&entry("resource \"aws_s3_bucket\" \"b\" {}")),
+            Some((ContentType::Code, Some("hcl".to_string())))
+        );
+        assert_eq!(plugins.classify(&entry("older copy")), Some((ContentType::Text, None)));
+        assert_eq!(plugins.classify(&entry("something else")), None);
+    }
+
+    #[test]
+    fn test_transforms_and_actions_are_registered() {
+        let plugins = load(&[(
+            "extras",
+            r#"register_transform("reverse", "Reverse", |text| { let out = ""; for c in text { out = `${c}${out}`; } out });
+               register_action("url", "archive", "Open in the web archive", |url| #{ open: "https://web.archive.org/web/" + url });"#,
+        )]);
+
+        let mut transforms = TransformRegistry::builtin();
+        plugins.register_transforms(&mut transforms);
+        assert_eq!(transforms.apply("reverse", "abc").unwrap(), "cba");
+
+        let mut actions = ActionRegistry::builtin();
+        plugins.register_actions(&mut actions);
+        assert_eq!(
+            actions.get("archive").unwrap().run("https://example.com").unwrap(),
+            ActionOutcome::Open("https://web.archive.org/web/https://example.com".into())
+        );
+        assert_eq!(plugins.describe()[0].1, vec!["transform reverse", "action archive (url)"]);
+    }
+
+    #[test]
+    fn test_budgets_stop_bad_plugins() {
+        let plugins = load(&[
+            ("broken", "register_filter(|entry| "),
+            ("bad_type", r#"register_action("nope", "x", "X", |text| text);"#),
+            ("endless", "register_filter(|entry| { loop {} });"),
+            ("greedy", r#"register_filter(|entry| { let s = "x"; loop { s += s; } });"#),
+            ("stuck_at_load", "loop {}"),
+        ]);
+        assert_eq!(plugins.describe().len(), 2);
+
+        let started = Instant::now();
+        assert_eq!(plugins.filter(entry("kept")).as_deref(), Some("kept"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Values a template is expanded with
+pub struct TemplateContext<'a> {
+    /// Content of the current default clipboard entry, for `{{clipboard}}`
+    pub clipboard: &'a str,
+    /// Answers to the `{{input:Label}}` prompts, keyed by label
+    pub inputs: &'a HashMap<String, String>,
+}
+
+/// A `{{kind:argument}}` placeholder found in a template
+struct Placeholder<'a> {
+    start: usize,
+    end: usize,
+    kind: &'a str,
+    argument: Option<&'a str>,
+}
+
+/// Labels of the `{{input:Label}}` placeholders in `template`, each once, in order
+pub fn input_labels(template: &str) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+
+    for placeholder in placeholders(template) {
+        if placeholder.kind == "input" {
+            let label = placeholder.argument.unwrap_or_default().to_string();
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+    }
+    labels
+}
+
+/// Resolve the placeholders in `template`.
+///
+/// Supported are `{{date:FORMAT}}` (strftime format, `%Y-%m-%d` by default),
+/// `{{clipboard}}`, `{{uuid}}`, `{{env:NAME}}` and `{{input:Label}}`. Unknown
+/// placeholders are left as they are.
+pub fn expand(template: &str, context: &TemplateContext) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut copied = 0;
+
+    for placeholder in placeholders(template) {
+        let Some(value) = resolve(&placeholder, context) else {
+            continue;
+        };
+
+        output.push_str(&template[copied..placeholder.start]);
+        output.push_str(&value);
+        copied = placeholder.end;
+    }
+
+    output.push_str(&template[copied..]);
+    output
+}
+
+fn resolve(placeholder: &Placeholder, context: &TemplateContext) -> Option<String> {
+    match placeholder.kind {
+        "date" => {
+            let format = placeholder.argument.unwrap_or("%Y-%m-%d");
+            let mut date = String::new();
+            // An invalid format is reported as a formatting error instead of a panic
+            write!(date, "{}", chrono::Local::now().format(format)).ok()?;
+            Some(date)
+        }
+        "clipboard" => Some(context.clipboard.to_string()),
+        "uuid" => Some(uuid::Uuid::new_v4().to_string()),
+        "env" => Some(std::env::var(placeholder.argument?).unwrap_or_default()),
+        "input" => Some(
+            context
+                .inputs
+                .get(placeholder.argument.unwrap_or_default())
+                .cloned()
+                .unwrap_or_default(),
+        ),
+        _ => None,
+    }
+}
+
+fn placeholders(template: &str) -> Vec<Placeholder<'_>> {
+    let mut found = Vec::new();
+    let mut position = 0;
+
+    while let Some(open) = template[position..].find("{{") {
+        let start = position + open;
+        let Some(close) = template[start + 2..].find("}}") else {
+            break;
+        };
+        let end = start + 2 + close + 2;
+
+        let inner = template[start + 2..end - 2].trim();
+        let (kind, argument) = match inner.split_once(':') {
+            Some((kind, argument)) => (kind.trim(), Some(argument.trim())),
+            None => (inner, None),
+        };
+        found.push(Placeholder { start, end, kind, argument });
+        position = end;
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_placeholders() {
+        std::env::set_var("CLIPPOO_TEMPLATE_TEST", "jane");
+        let inputs = HashMap::from([("Ticket ID".to_string(), "ABC-42".to_string())]);
+        let context = TemplateContext { clipboard: "copied text", inputs: &inputs };
+
+        assert_eq!(
+            expand("{{input:Ticket ID}} by {{env:CLIPPOO_TEMPLATE_TEST}}: {{ clipboard }}", &context),
+            "ABC-42 by jane: copied text"
+        );
+        assert_eq!(expand("{{date:%Y}}", &context), chrono::Local::now().format("%Y").to_string());
+        assert_eq!(expand("{{date}}", &context), chrono::Local::now().format("%Y-%m-%d").to_string());
+
+        let id = expand("{{uuid}}", &context);
+        assert_eq!(id.len(), 36);
+        assert_ne!(id, expand("{{uuid}}", &context));
+    }
+
+    #[test]
+    fn test_unknown_placeholders_are_kept() {
+        let inputs = HashMap::new();
+        let context = TemplateContext { clipboard: "", inputs: &inputs };
+
+        assert_eq!(expand("{{nope}} {{date:%Q}} {{unclosed", &context), "{{nope}} {{date:%Q}} {{unclosed");
+        assert_eq!(expand("no placeholders", &context), "no placeholders");
+    }
+
+    #[test]
+    fn test_input_labels() {
+        assert_eq!(
+            input_labels("{{input:Name}} {{date}} {{input: Ticket ID }} {{input:Name}}"),
+            vec!["Name".to_string(), "Ticket ID".to_string()]
+        );
+    }
+}
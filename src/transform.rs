@@ -0,0 +1,233 @@
+use anyhow::Result;
+use base64::Engine;
+
+type TransformFn = Box<dyn Fn(&str) -> Result<String> + Send + Sync>;
+
+/// A named text transformation offered by "paste as…"
+pub struct Transform {
+    pub name: String,
+    pub label: String,
+    apply: TransformFn,
+}
+
+impl Transform {
+    pub fn apply(&self, input: &str) -> Result<String> {
+        (self.apply)(input)
+    }
+}
+
+/// Registry of the transformations available before pasting
+pub struct TransformRegistry {
+    transforms: Vec<Transform>,
+}
+
+impl TransformRegistry {
+    /// Registry holding the built-in transformations
+    pub fn builtin() -> Self {
+        let mut registry = Self { transforms: Vec::new() };
+
+        registry.register("trim", "Trim whitespace", |s| Ok(s.trim().to_string()));
+        registry.register("uppercase", "UPPERCASE", |s| Ok(s.to_uppercase()));
+        registry.register("lowercase", "lowercase", |s| Ok(s.to_lowercase()));
+        registry.register("title_case", "Title Case", |s| Ok(title_case(s)));
+        registry.register("strip_formatting", "Strip formatting", |s| Ok(strip_formatting(s)));
+        registry.register("url_encode", "URL-encode", |s| Ok(url_encode(s)));
+        registry.register("url_decode", "URL-decode", url_decode);
+        registry.register("base64_encode", "Base64-encode", |s| {
+            Ok(base64::engine::general_purpose::STANDARD.encode(s))
+        });
+        registry.register("base64_decode", "Base64-decode", base64_decode);
+        registry.register("json_pretty", "Pretty-print JSON", |s| {
+            let value: serde_json::Value = serde_json::from_str(s)?;
+            Ok(serde_json::to_string_pretty(&value)?)
+        });
+        registry.register("json_minify", "Minify JSON", |s| {
+            let value: serde_json::Value = serde_json::from_str(s)?;
+            Ok(serde_json::to_string(&value)?)
+        });
+        registry.register("shell_escape", "Escape for shell", |s| Ok(shell_escape(s)));
+        registry.register("sql_escape", "Escape as SQL string", |s| {
+            Ok(format!("'{}'", s.replace('\'', "''")))
+        });
+
+        registry
+    }
+
+    /// Add a transformation, replacing any existing one with the same name
+    pub fn register<F>(&mut self, name: &str, label: &str, apply: F)
+    where
+        F: Fn(&str) -> Result<String> + Send + Sync + 'static,
+    {
+        self.transforms.retain(|t| t.name != name);
+        self.transforms.push(Transform {
+            name: name.to_string(),
+            label: label.to_string(),
+            apply: Box::new(apply),
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Transform> {
+        self.transforms.iter().find(|t| t.name == name)
+    }
+
+    pub fn all(&self) -> &[Transform] {
+        &self.transforms
+    }
+
+    pub fn apply(&self, name: &str, input: &str) -> Result<String> {
+        let transform = self
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown transform '{}'", name))?;
+        transform.apply(input)
+    }
+}
+
+fn title_case(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut at_word_start = true;
+
+    for c in input.chars() {
+        if c.is_alphanumeric() {
+            if at_word_start {
+                result.extend(c.to_uppercase());
+            } else {
+                result.extend(c.to_lowercase());
+            }
+            at_word_start = false;
+        } else {
+            result.push(c);
+            at_word_start = c.is_whitespace() || c == '-' || c == '_';
+        }
+    }
+
+    result
+}
+
+/// Remove terminal colour codes, HTML tags and typographic characters that
+/// tend to come along when copying from web pages or terminals
+fn strip_formatting(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // ANSI escape sequence: ESC [ params final-byte
+            '\u{1b}' => {
+                if chars.peek() == Some(&'[') {
+                    chars.next();
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+            }
+            // HTML tag, only when it looks like one
+            '<' if chars.peek().is_some_and(|c| c.is_ascii_alphabetic() || *c == '/' || *c == '!') => {
+                for c in chars.by_ref() {
+                    if c == '>' {
+                        break;
+                    }
+                }
+            }
+            '\u{a0}' => result.push(' '),
+            '\u{200b}' | '\u{200c}' | '\u{200d}' | '\u{feff}' => {}
+            '\u{2018}' | '\u{2019}' => result.push('\''),
+            '\u{201c}' | '\u{201d}' => result.push('"'),
+            _ => result.push(c),
+        }
+    }
+
+    result
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn url_encode(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    result
+}
+
+fn url_decode(input: &str) -> Result<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| anyhow::anyhow!("Invalid percent-encoding at position {}", i))?;
+            decoded.push(hex);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| anyhow::anyhow!("Decoded text is not valid UTF-8"))
+}
+
+fn base64_decode(input: &str) -> Result<String> {
+    let compact: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(compact)
+        .map_err(|e| anyhow::anyhow!("Invalid base64: {}", e))?;
+    String::from_utf8(bytes).map_err(|_| anyhow::anyhow!("Decoded data is not text"))
+}
+
+/// Quote for POSIX shells: wrap in single quotes, closing and reopening them
+/// around any single quote in the text
+fn shell_escape(input: &str) -> String {
+    format!("'{}'", input.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_transforms() {
+        let registry = TransformRegistry::builtin();
+
+        assert_eq!(registry.apply("trim", "  hi \n").unwrap(), "hi");
+        assert_eq!(registry.apply("title_case", "hello wORLD").unwrap(), "Hello World");
+        assert_eq!(registry.apply("url_encode", "a b&c=ü").unwrap(), "a%20b%26c%3D%C3%BC");
+        assert_eq!(registry.apply("url_decode", "a%20b%26c%3D%C3%BC").unwrap(), "a b&c=ü");
+        assert_eq!(registry.apply("base64_encode", "hello").unwrap(), "aGVsbG8=");
+        assert_eq!(registry.apply("base64_decode", "aGVs\nbG8=").unwrap(), "hello");
+        assert_eq!(registry.apply("json_minify", "{ \"b\": 1,\n \"a\": [1, 2] }").unwrap(), r#"{"b":1,"a":[1,2]}"#);
+        assert_eq!(registry.apply("shell_escape", "it's").unwrap(), r"'it'\''s'");
+        assert_eq!(registry.apply("sql_escape", "it's").unwrap(), "'it''s'");
+        assert_eq!(
+            registry.apply("strip_formatting", "\u{1b}[1;31m<b>bold</b>\u{1b}[0m &amp; 1 < 2").unwrap(),
+            "bold & 1 < 2"
+        );
+    }
+
+    #[test]
+    fn test_invalid_input_is_an_error() {
+        let registry = TransformRegistry::builtin();
+
+        assert!(registry.apply("json_pretty", "not json").is_err());
+        assert!(registry.apply("url_decode", "%zz").is_err());
+        assert!(registry.apply("base64_decode", "!!!").is_err());
+        assert!(registry.apply("no_such_transform", "x").is_err());
+    }
+}
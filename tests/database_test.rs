@@ -2,6 +2,12 @@ use anyhow::Result;
 use sqlx::sqlite::SqlitePoolOptions;
 use tempfile::TempDir;
 
+#[path = "../src/database/mod.rs"]
+#[allow(dead_code)]
+mod database;
+use database::sync::Change;
+use database::{Database, DedupPolicy, EntrySource, Selection};
+
 #[tokio::test]
 async fn test_sqlite_basic_operations() -> Result<()> {
     // Create a temporary directory for test database
@@ -99,6 +105,567 @@ async fn test_clipboard_entry_operations() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_paste_stack_order() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let first = db.add_entry("first".to_string()).await?;
+    let second = db.add_entry("second".to_string()).await?;
+    db.push_stack(first).await?;
+    db.push_stack(second).await?;
+    
+    let (_, head) = db.get_stack_head().await?.expect("stack should have a head");
+    assert_eq!(head.content, "first");
+    
+    let popped = db.pop_stack().await?.expect("stack should not be empty");
+    assert_eq!(popped.id, first);
+    
+    let remaining = db.get_stack_entries().await?;
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].content, "second");
+    
+    db.clear_stack().await?;
+    assert!(db.pop_stack().await?.is_none());
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_entry_keeps_content_unique() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let original = db.add_entry("helo world".to_string()).await?;
+    assert_eq!(db.update_entry(original, "hello world".to_string()).await?, original);
+    assert_eq!(db.get_entry(original).await?.unwrap().content, "hello world");
+    
+    // Editing into content that already exists folds the edited row into it
+    let duplicate = db.add_entry("hello world!".to_string()).await?;
+    assert_eq!(db.update_entry(duplicate, "hello world".to_string()).await?, original);
+    assert!(db.get_entry(duplicate).await?.is_none());
+    
+    let survivor = db.get_entry(original).await?.unwrap();
+    assert!(survivor.is_default, "default flag should move to the surviving entry");
+    assert_eq!(db.get_recent_entries(10).await?.len(), 1);
+    
+    assert!(db.update_entry(9999, "missing".to_string()).await.is_err());
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_snippets_are_kept_apart_from_history() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let id = db.save_snippet("sql", "count rows", Some("cnt"), "SELECT COUNT(*) FROM t").await?;
+    
+    // Saving under the same folder and name replaces the snippet
+    let same = db.save_snippet("sql", "count rows", Some("cnt"), "SELECT COUNT(*) FROM users").await?;
+    assert_eq!(same, id);
+    
+    // History cleanup must not touch snippets
+    for i in 0..60 {
+        db.add_entry(format!("entry {}", i)).await?;
+    }
+    
+    let snippets = db.get_snippets().await?;
+    assert_eq!(snippets.len(), 1);
+    assert_eq!(snippets[0].content, "SELECT COUNT(*) FROM users");
+    
+    let by_keyword = db.find_snippet_by_keyword("cnt").await?.expect("keyword should match");
+    assert_eq!(by_keyword.id, id);
+    
+    db.delete_snippet(id).await?;
+    assert!(db.get_snippets().await?.is_empty());
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_snippet_keyword_moves_to_the_newer_snippet() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let old = db.save_snippet("mail", "signature", Some("sig"), "Regards").await?;
+    let new = db.save_snippet("mail", "short signature", Some("sig"), "Cheers").await?;
+    
+    // The keyword changes hands instead of failing the save
+    assert_eq!(db.find_snippet_by_keyword("sig").await?.expect("keyword should match").id, new);
+    let snippets = db.get_snippets().await?;
+    assert_eq!(snippets.iter().find(|s| s.id == old).unwrap().keyword, None);
+    
+    // An import reusing keywords saves everything, the last one keeping the keyword
+    let saved = db.save_snippets([
+        ("sql", "count rows", Some("cnt"), "SELECT COUNT(*) FROM t"),
+        ("sql", "count users", Some("cnt"), "SELECT COUNT(*) FROM users"),
+    ]).await?;
+    assert_eq!(saved, 2);
+    assert_eq!(db.get_snippets().await?.len(), 4);
+    assert_eq!(db.find_snippet_by_keyword("cnt").await?.unwrap().name, "count users");
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tagged_entries_survive_cleanup() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let incident = db.add_entry("stack trace".to_string()).await?;
+    db.tag_entry(incident, "Incident-42").await?;
+    db.tag_entry(incident, "#backend").await?;
+    
+    for i in 0..60 {
+        db.add_entry(format!("entry {}", i)).await?;
+    }
+    
+    // Tags match without case and the entry outlives the recent window
+    let tagged = db.entries_with_tag("incident-42").await?;
+    assert_eq!(tagged.len(), 1);
+    assert_eq!(tagged[0].id, incident);
+    assert_eq!(db.get_entry_tags().await?[&incident], vec!["backend", "Incident-42"]);
+    
+    db.untag_entry(incident, "backend").await?;
+    assert!(db.entries_with_tag("backend").await?.is_empty());
+    assert_eq!(db.get_entry_tags().await?[&incident], vec!["Incident-42"]);
+    
+    assert!(db.tag_entry(incident, " # ").await.is_err());
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_clear_history_keeps_tagged_entries() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let kept = db.add_entry("keep me".to_string()).await?;
+    db.tag_entry(kept, "keep").await?;
+    let stacked = db.add_entry("stacked".to_string()).await?;
+    db.push_stack(stacked).await?;
+    db.add_entry("plain".to_string()).await?;
+    
+    assert_eq!(db.clear_history().await?, 2);
+    
+    let remaining = db.get_recent_entries(10).await?;
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, kept);
+    assert!(db.get_stack_entries().await?.is_empty());
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_frecency_ranks_used_entries_first() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let favourite = db.add_entry("used a lot".to_string()).await?;
+    db.add_entry("copied once".to_string()).await?;
+    let latest = db.add_entry("copied last".to_string()).await?;
+    
+    for _ in 0..3 {
+        db.set_default_entry(favourite).await?;
+    }
+    // Re-capturing the selected entry doesn't count as another use
+    db.add_entry("used a lot".to_string()).await?;
+    db.set_default_entry(latest).await?;
+    
+    let frecent = db.get_frecent_entries(10).await?;
+    assert_eq!(frecent.len(), 3);
+    assert_eq!(frecent[0].id, favourite);
+    assert_eq!(frecent[1].id, latest);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_old_database_is_upgraded() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("test.db");
+    std::fs::File::create(&db_path)?;
+    
+    // History table as created by the first release
+    let pool = SqlitePoolOptions::new()
+        .connect(&format!("sqlite:{}", db_path.display()))
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE clipboard_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            content TEXT NOT NULL UNIQUE,
+            timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            is_default BOOLEAN NOT NULL DEFAULT FALSE
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("INSERT INTO clipboard_history (content, is_default) VALUES ('old entry', TRUE)")
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+    
+    let db = Database::open(&db_path).await?;
+    let old = db.get_default_entry().await?.expect("old entry should be kept");
+    db.set_default_entry(old.id).await?;
+    
+    let frecent = db.get_frecent_entries(10).await?;
+    assert_eq!(frecent.len(), 1);
+    assert_eq!(frecent[0].content, "old entry");
+    
+    // The UNIQUE content constraint is gone and old entries are deduplicated by hash
+    let unique = sqlx::query_as::<_, (String,)>("SELECT name FROM pragma_index_list('clipboard_history') WHERE origin = 'u'")
+        .fetch_all(&SqlitePoolOptions::new().connect(&format!("sqlite:{}", db_path.display())).await?)
+        .await?;
+    assert!(unique.is_empty());
+    assert_eq!(db.add_entry("old entry".to_string()).await?, old.id);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_near_duplicates_are_collapsed() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("test.db");
+    let mut db = Database::open(&db_path).await?;
+    
+    // Without a policy only identical content is a duplicate
+    let crlf = db.add_entry("line one\r\nline two\r\n".to_string()).await?;
+    let tagged = db.add_entry("  line one\nline two".to_string()).await?;
+    db.tag_entry(tagged, "keep").await?;
+    let lf = db.add_entry("line one\nline two\n".to_string()).await?;
+    assert_eq!(db.get_recent_entries(10).await?.len(), 3);
+    
+    // Turning it on merges them into the newest, keeping the tags
+    db.set_dedup_policy(DedupPolicy::default()).await?;
+    let entries = db.get_recent_entries(10).await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].id, lf);
+    assert!(db.get_entry(crlf).await?.is_none());
+    assert_eq!(db.entries_with_tag("keep").await?[0].id, lf);
+    
+    // A near-duplicate updates the entry with its own form, also after reopening
+    drop(db);
+    let db = Database::open(&db_path).await?;
+    assert_eq!(db.add_entry("line one\r\nline two".to_string()).await?, lf);
+    assert_eq!(db.get_entry(lf).await?.unwrap().content, "line one\r\nline two");
+    
+    let composed = db.add_entry("caf\u{e9}".to_string()).await?;
+    assert_eq!(db.add_entry("cafe\u{301}".to_string()).await?, composed);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_imported_entries_keep_their_timestamps() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    let current = db.add_entry("copied here".to_string()).await?;
+    
+    let at = |hour: u32| chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2024, 5, 1, hour, 0, 0).unwrap();
+    let entries = vec![
+        ("imported".to_string(), at(9)),
+        ("copied here".to_string(), at(10)),
+        ("imported".to_string(), at(11)),
+    ];
+    assert_eq!(db.import_entries(entries).await?, 1);
+    
+    // Duplicates keep the newer timestamp and the current entry stays the default
+    let entries = db.get_recent_entries(10).await?;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].id, current);
+    assert!(entries[0].is_default);
+    assert_eq!(entries[1].content, "imported");
+    assert_eq!(entries[1].timestamp, at(11));
+    assert!(!entries[1].is_default);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_imported_entries_survive_cleanup() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    for i in 0..50 {
+        db.add_entry(format!("copied {}", i)).await?;
+    }
+    
+    // Older than everything copied here, so they would be the first to go
+    let at = |minute: i64| chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2024, 5, 1, 0, 0, 0).unwrap()
+        + chrono::Duration::minutes(minute);
+    let entries = (0..60).map(|i| (format!("imported {}", i), at(i))).collect();
+    assert_eq!(db.import_entries(entries).await?, 60);
+    assert_eq!(db.export_entries().await?.len(), 110);
+    
+    // New copies only push out the entries copied here
+    db.add_entry("one more".to_string()).await?;
+    let records = db.export_entries().await?;
+    assert_eq!(records.len(), 110);
+    assert_eq!(records.iter().filter(|record| record.content.starts_with("imported")).count(), 60);
+    assert_eq!(records.iter().filter(|record| !record.content.starts_with("imported")).count(), 50);
+    
+    // Clearing the history still removes them
+    db.clear_history().await?;
+    assert!(db.export_entries().await?.is_empty());
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_restored_entries_merge_their_usage() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let source = Database::open(&temp_dir.path().join("source.db")).await?;
+    let browser = EntrySource { app: Some("firefox".to_string()), ..EntrySource::default() };
+    let id = source.add_entry_with_source("shared".to_string(), &browser).await?;
+    source.set_default_entry(id).await?;
+    source.set_default_entry(id).await?;
+    source.tag_entry(id, "keep").await?;
+    source.add_entry("only in the backup".to_string()).await?;
+    let records = source.export_entries().await?;
+    let use_count = records[0].use_count;
+    assert!(use_count > 1);
+    assert_eq!(records[0].tags, vec!["keep".to_string()]);
+    
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    let current = db.add_entry("shared".to_string()).await?;
+    db.set_default_entry(current).await?;
+    assert_eq!(db.restore_entries(records.clone()).await?, 1);
+    assert_eq!(db.restore_entries(records).await?, 0);
+    
+    // The existing entry gains the backup's usage, metadata and tags but
+    // the current default stays
+    let entry = db.get_entry(current).await?.unwrap();
+    assert!(entry.is_default);
+    assert_eq!(entry.source_app.as_deref(), Some("firefox"));
+    assert_eq!(db.entries_with_tag("keep").await?[0].id, current);
+    let merged = db.export_entries().await?;
+    assert_eq!(merged.len(), 2);
+    assert_eq!(merged.iter().find(|record| record.id == current).unwrap().use_count, use_count);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_restored_entries_survive_cleanup() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let source = Database::open(&temp_dir.path().join("source.db")).await?;
+    
+    // A backup from long before everything copied here
+    let at = |minute: i64| chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2024, 5, 1, 0, 0, 0).unwrap()
+        + chrono::Duration::minutes(minute);
+    source.import_entries((0..60).map(|i| (format!("backed up {}", i), at(i))).collect()).await?;
+    let records = source.export_entries().await?;
+    assert_eq!(records.len(), 60);
+    
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    for i in 0..50 {
+        db.add_entry(format!("copied {}", i)).await?;
+    }
+    assert_eq!(db.restore_entries(records).await?, 60);
+    assert_eq!(db.export_entries().await?.len(), 110);
+    
+    // New copies only push out the entries copied here
+    db.add_entry("one more".to_string()).await?;
+    let restored = db.export_entries().await?;
+    assert_eq!(restored.len(), 110);
+    assert_eq!(restored.iter().filter(|record| record.content.starts_with("backed up")).count(), 60);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_latest_synced_change_wins() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    let change = |clock: i64, content: Option<&str>| -> Change {
+        let entry = content.map_or(String::new(), |content| {
+            format!(r#","entry":{{"content":"{}","timestamp":"2024-05-01T09:00:00Z"}}"#, content)
+        });
+        serde_json::from_str(&format!(r#"{{"uuid":"a1","clock":{},"machine":"laptop"{}}}"#, clock, entry)).unwrap()
+    };
+    let contents = || async {
+        let entries = db.get_recent_entries(10).await.unwrap();
+        entries.into_iter().map(|entry| entry.content).collect::<Vec<_>>()
+    };
+    
+    assert_eq!(db.apply_changes(vec![change(5, Some("first")), change(3, Some("older"))]).await?, 1);
+    assert_eq!(contents().await, vec!["first"]);
+    
+    // A removal keeps older versions of the entry from coming back
+    assert_eq!(db.apply_changes(vec![change(6, None), change(4, Some("older"))]).await?, 1);
+    assert!(contents().await.is_empty());
+    assert_eq!(db.apply_changes(vec![change(7, Some("newer"))]).await?, 1);
+    assert_eq!(contents().await, vec!["newer"]);
+    
+    // Local changes are numbered after the changes seen so far
+    db.update_entry(db.get_recent_entries(1).await?[0].id, "edited".to_string()).await?;
+    let pending = db.collect_changes("desktop").await?;
+    db.mark_sent(&pending).await?;
+    let sent = pending.changes;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].uuid, "a1");
+    assert_eq!(sent[0].clock, 8);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_large_entries_are_stored_in_blobs() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?.with_blob_threshold(16);
+    let blobs = temp_dir.path().join("blobs");
+    
+    let large = "é".repeat(5000);
+    let id = db.add_entry(large.clone()).await?;
+    let entry = db.get_entry(id).await?.unwrap();
+    assert_eq!(entry.content, large);
+    assert_eq!(entry.blob.as_deref(), Some(database::content_hash(&large).as_str()));
+    assert!(blobs.join(entry.blob.unwrap()).exists());
+    
+    // Dedup goes by hash, for blobs too
+    assert_eq!(db.add_entry(large).await?, id);
+    assert_eq!(db.add_entry("small".to_string()).await?, id + 1);
+    assert!(db.get_entry(id + 1).await?.unwrap().blob.is_none());
+    
+    // Files of removed entries are deleted once they are too old to be about
+    // to get an entry from another process; files being written are kept
+    db.update_entry(id, "x".repeat(100)).await?;
+    assert_eq!(std::fs::read_dir(&blobs)?.count(), 2);
+    std::fs::write(blobs.join("other.partial"), "")?;
+    let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    for file in std::fs::read_dir(&blobs)? {
+        std::fs::File::options().write(true).open(file?.path())?.set_modified(an_hour_ago)?;
+    }
+    std::fs::write(blobs.join("just-written"), "")?;
+    db.clear_history().await?;
+    let mut left: Vec<String> = std::fs::read_dir(&blobs)?
+        .map(|file| Ok(file?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_>>()?;
+    left.sort();
+    assert_eq!(left, vec!["just-written", "other.partial"]);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_primary_selection_entries() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let primary = EntrySource { selection: Selection::Primary, ..EntrySource::default() };
+    let copied = db.add_entry("copied text".to_string()).await?;
+    let selected = db.add_entry_with_source("selected text".to_string(), &primary).await?;
+    assert_eq!(db.get_entry(selected).await?.unwrap().selection, Selection::Primary);
+    
+    // The clipboard still holds the copied text, so that stays the default
+    assert!(!db.get_entry(selected).await?.unwrap().is_default);
+    assert_eq!(db.get_default_entry().await?.map(|entry| entry.id), Some(copied));
+    
+    // Copying the selected text makes it a clipboard entry, and selecting
+    // copied text again doesn't turn it back
+    db.add_entry("something else".to_string()).await?;
+    db.add_entry("selected text".to_string()).await?;
+    db.add_entry_with_source("selected text".to_string(), &primary).await?;
+    assert_eq!(db.get_entry(selected).await?.unwrap().selection, Selection::Clipboard);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_entry_source_is_recorded() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let browser = EntrySource {
+        app: Some("firefox".to_string()),
+        window_title: Some("Docs - Mozilla Firefox".to_string()),
+        url: Some("https://example.com/docs".to_string()),
+        ..EntrySource::default()
+    };
+    let terminal = EntrySource {
+        app: Some("foot".to_string()),
+        window_title: Some("~".to_string()),
+        url: None,
+        ..EntrySource::default()
+    };
+    
+    let id = db.add_entry_with_source("snippet of docs".to_string(), &browser).await?;
+    let entry = db.get_entry(id).await?.unwrap();
+    assert_eq!(entry.source_app.as_deref(), Some("firefox"));
+    assert_eq!(entry.source_url.as_deref(), Some("https://example.com/docs"));
+    
+    // Re-capturing the current entry happens when it is pasted, so the focused
+    // window is the paste target and the source is kept
+    db.add_entry_with_source("snippet of docs".to_string(), &terminal).await?;
+    assert_eq!(db.get_entry(id).await?.unwrap().source_app.as_deref(), Some("firefox"));
+    
+    // Copying it again from elsewhere records the new source
+    db.add_entry("something else".to_string()).await?;
+    db.add_entry_with_source("snippet of docs".to_string(), &terminal).await?;
+    let entry = db.get_entry(id).await?.unwrap();
+    assert_eq!(entry.source_app.as_deref(), Some("foot"));
+    assert_eq!(entry.source_url, None);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_content_type_is_cleared_on_edit() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    
+    let id = db.add_entry("fn main() {}".to_string()).await?;
+    db.set_content_type(id, "code", Some("rust")).await?;
+    
+    let entry = db.get_entry(id).await?.unwrap();
+    assert_eq!(entry.content_type.as_deref(), Some("code"));
+    assert_eq!(entry.language.as_deref(), Some("rust"));
+    
+    db.update_entry(id, "just some text".to_string()).await?;
+    let entry = db.get_entry(id).await?.unwrap();
+    assert_eq!(entry.content_type, None);
+    assert_eq!(entry.language, None);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_expired_entries_are_purged() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db = Database::open(&temp_dir.path().join("test.db")).await?;
+    let now = chrono::Utc::now();
+
+    let kept = db.add_entry("meeting notes".to_string()).await?;
+    let later = db.add_entry("api token".to_string()).await?;
+    let code = db.add_entry("482913".to_string()).await?;
+    db.tag_entry(code, "login").await?;
+    db.set_expiry(kept, Some(now - chrono::Duration::seconds(5))).await?;
+    db.set_expiry(kept, None).await?;
+    db.set_expiry(later, Some(now + chrono::Duration::hours(1))).await?;
+    db.set_expiry(code, Some(now - chrono::Duration::seconds(1))).await?;
+    assert!(db.set_expiry(1000, None).await.is_err());
+
+    let entry = db.get_entry(later).await?.unwrap();
+    assert_eq!(entry.expires_at.map(|at| at.timestamp()), Some((now + chrono::Duration::hours(1)).timestamp()));
+
+    // Tags don't keep an expired entry, and the caller learns it was the default
+    let expired = db.purge_expired().await?;
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].id, code);
+    assert!(expired[0].is_default);
+    assert!(db.get_entry(code).await?.is_none());
+    assert!(db.entries_with_tag("login").await?.is_empty());
+    assert_eq!(db.get_default_entry().await?.map(|entry| entry.id), None);
+    assert_eq!(db.get_recent_entries(10).await?.len(), 2);
+
+    assert!(db.purge_expired().await?.is_empty());
+
+    Ok(())
+}
+
 #[test]
 fn test_basic_setup() {
     // Basic sanity test
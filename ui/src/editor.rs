@@ -0,0 +1,84 @@
+use gtk4::prelude::*;
+use gtk4::{
+    gdk, glib, ApplicationWindow, Box, EventControllerKey, Label, Orientation, PropagationPhase,
+    ScrolledWindow, TextView, Window, WrapMode,
+};
+
+/// How the edited text should be stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaveMode {
+    /// Replace the content of the edited entry
+    Update,
+    /// Keep the edited entry and add the text as a new one
+    AsNew,
+}
+
+/// Open `content` in an editable text view on top of the popup.
+///
+/// Ctrl+S saves, Ctrl+Shift+S saves as a new entry and Escape discards the changes.
+pub fn open_editor<F>(parent: &ApplicationWindow, content: &str, on_save: F)
+where
+    F: Fn(String, SaveMode) + 'static,
+{
+    let window = Window::builder()
+        .transient_for(parent)
+        .title("Edit entry")
+        .default_width(560)
+        .default_height(420)
+        .decorated(false)
+        .modal(true)
+        .build();
+    window.add_css_class("clipboard-popup");
+
+    let vbox = Box::new(Orientation::Vertical, 8);
+    vbox.add_css_class("popup-container");
+
+    let text_view = TextView::new();
+    text_view.set_wrap_mode(WrapMode::WordChar);
+    text_view.set_monospace(true);
+    text_view.add_css_class("editor-text");
+    text_view.buffer().set_text(content);
+
+    let scrolled = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .vexpand(true)
+        .child(&text_view)
+        .build();
+    vbox.append(&scrolled);
+
+    let hint = Label::new(Some("Ctrl+S save  ·  Ctrl+Shift+S save as new  ·  Esc cancel"));
+    hint.add_css_class("editor-hint");
+    vbox.append(&hint);
+
+    window.set_child(Some(&vbox));
+
+    // Capture phase so the shortcuts win over the text view's own bindings
+    let key_controller = EventControllerKey::new();
+    key_controller.set_propagation_phase(PropagationPhase::Capture);
+
+    let editor_window = window.clone();
+    let buffer = text_view.buffer();
+    key_controller.connect_key_pressed(move |_, keyval, _, modifiers| match keyval {
+        gdk::Key::Escape => {
+            editor_window.close();
+            glib::Propagation::Stop
+        }
+        gdk::Key::s | gdk::Key::S if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+            let mode = if modifiers.contains(gdk::ModifierType::SHIFT_MASK) {
+                SaveMode::AsNew
+            } else {
+                SaveMode::Update
+            };
+            let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+
+            editor_window.close();
+            on_save(text.to_string(), mode);
+            glib::Propagation::Stop
+        }
+        _ => glib::Propagation::Proceed,
+    });
+    window.add_controller(key_controller);
+
+    window.present();
+    text_view.grab_focus();
+}
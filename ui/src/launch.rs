@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+use gtk4::gio;
+use gtk4::prelude::*;
+use log::debug;
+use std::process::{Command, Stdio};
+
+/// Open a URI with the user's default application.
+///
+/// xdg-open picks the handler the desktop is configured with, and goes
+/// through the OpenURI portal when running sandboxed.
+pub fn open_uri(uri: &str) -> Result<()> {
+    Command::new("xdg-open")
+        .arg(uri)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run xdg-open")?;
+    Ok(())
+}
+
+/// Show a file selected in the file manager, or open its folder when no file
+/// manager implements `org.freedesktop.FileManager1`
+pub fn reveal_uri(uri: &str) -> Result<()> {
+    let shown = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE).and_then(|bus| {
+        bus.call_sync(
+            Some("org.freedesktop.FileManager1"),
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1",
+            "ShowItems",
+            Some(&(vec![uri.to_string()], "").to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            2000,
+            gio::Cancellable::NONE,
+        )
+    });
+
+    match shown {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            debug!("FileManager1.ShowItems failed: {}", e);
+            let folder = uri.rsplit_once('/').map_or(uri, |(folder, _)| folder);
+            open_uri(&format!("{}/", folder))
+        }
+    }
+}
@@ -3,12 +3,43 @@ use gtk4::prelude::*;
 use gtk4::{Application, CssProvider};
 use log::info;
 
+mod editor;
+mod launch;
+mod paste;
 mod popup;
+mod prompt;
+mod row_menu;
+mod snippets;
 use popup::ClipboardPopup;
 
+#[path = "../../src/actions.rs"]
+mod actions;
+
+#[path = "../../src/classify.rs"]
+mod classify;
+
+#[path = "../../src/config.rs"]
+mod config;
+
 #[path = "../../src/database/mod.rs"]
 mod database;
 
+// Capture and clear hooks are run by the daemon
+#[path = "../../src/hooks.rs"]
+#[allow(dead_code)]
+mod hooks;
+
+// Filters and classifiers are run by the daemon
+#[path = "../../src/plugins.rs"]
+#[allow(dead_code)]
+mod plugins;
+
+#[path = "../../src/transform.rs"]
+mod transform;
+
+#[path = "../../src/template.rs"]
+mod template;
+
 const APP_ID: &str = "com.clippoo.ClipboardManager";
 
 fn main() -> Result<()> {
@@ -0,0 +1,190 @@
+use anyhow::Result;
+use log::{debug, error, info, warn};
+
+pub fn copy_to_clipboard(content: &str) -> Result<()> {
+    use arboard::Clipboard;
+    
+    let mut clipboard = Clipboard::new()
+        .map_err(|e| anyhow::anyhow!("Failed to access clipboard: {}", e))?;
+    
+    clipboard.set_text(content)
+        .map_err(|e| anyhow::anyhow!("Failed to set clipboard content: {}", e))?;
+    
+    info!("Copied to clipboard using arboard");
+    Ok(())
+}
+
+pub fn spawn_auto_paste() {
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+    
+    // Spawn a thread to handle auto-paste after a short delay
+    thread::spawn(|| {
+        // Small delay to ensure window closes and focus returns
+        thread::sleep(Duration::from_millis(200));
+        
+        // Always use Ctrl+Shift+V for paste
+        info!("Executing auto-paste with Ctrl+Shift+V");
+        
+        // Execute ydotool
+        match Command::new("ydotool")
+            .args(&["key", "ctrl+shift+v"])
+            .output()
+        {
+            Ok(output) => {
+                if output.status.success() {
+                    info!("Auto-paste executed successfully");
+                } else {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    warn!("ydotool failed: {}", stderr);
+                }
+            }
+            Err(e) => {
+                warn!("Failed to execute ydotool: {}. Trying wtype fallback...", e);
+                
+                // Try wtype as fallback
+                if let Err(e) = Command::new("wtype")
+                    .args(&["-M", "ctrl", "-M", "shift", "-P", "v", "-m", "shift", "-m", "ctrl"])
+                    .output() 
+                {
+                    error!("Both ydotool and wtype failed: {}", e);
+                }
+            }
+        }
+    });
+}
+
+pub fn simulate_paste() -> Result<()> {
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+    
+    // Small delay to ensure window closes first
+    thread::sleep(Duration::from_millis(200));
+    
+    // Try ydotool first with regular Ctrl+V
+    info!("Attempting to auto-paste with ydotool...");
+    let ydotool_result = Command::new("ydotool")
+        .args(&["key", "ctrl+v"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .output();
+    
+    match ydotool_result {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            info!("ydotool exit status: {}, stdout: {}, stderr: {}", output.status.success(), stdout, stderr);
+            
+            if output.status.success() {
+                info!("Pasted using ydotool");
+                return Ok(());
+            } else {
+                warn!("ydotool failed with stderr: {}", stderr);
+            }
+        }
+        Err(e) => {
+            warn!("ydotool not found or failed to run: {}", e);
+        }
+    }
+    
+    // Fallback to wtype
+    let wtype_result = Command::new("wtype")
+        .args(&["-M", "ctrl", "-P", "v", "-m", "ctrl"])
+        .stderr(std::process::Stdio::piped())
+        .output();
+    
+    match wtype_result {
+        Ok(output) => {
+            if output.status.success() {
+                info!("Pasted using wtype");
+                return Ok(());
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                debug!("wtype failed: {}", stderr);
+            }
+        }
+        Err(e) => {
+            debug!("wtype not found or failed to run: {}", e);
+        }
+    }
+    
+    // If both fail, just log a warning but don't error out
+    // The content is already in the clipboard, user can paste manually
+    warn!("Auto-paste failed. Content is in clipboard - press Ctrl+V to paste manually.");
+    Ok(())
+}
+
+/// Type `content` into the focused window as synthesized keystrokes, for
+/// applications that refuse pasted input.
+pub fn spawn_type_out(content: String, delay_ms: u64) {
+    use std::thread;
+    use std::time::Duration;
+
+    thread::spawn(move || {
+        // Same delay as auto-paste so the popup is gone before typing starts
+        thread::sleep(Duration::from_millis(200));
+
+        // Typing "\r" would insert a stray character in most applications
+        let text = content.replace("\r\n", "\n").replace('\r', "\n");
+
+        info!("Typing out {} characters with {}ms key delay", text.chars().count(), delay_ms);
+
+        // ydotool can only type characters present in the active keyboard layout,
+        // so non-ASCII text goes straight to wtype, which handles any Unicode
+        if text.is_ascii() {
+            match type_with_ydotool(&text, delay_ms) {
+                Ok(()) => {
+                    info!("Type-out executed successfully");
+                    return;
+                }
+                Err(e) => warn!("ydotool type failed: {}. Trying wtype fallback...", e),
+            }
+        }
+
+        match type_with_wtype(&text, delay_ms) {
+            Ok(()) => info!("Type-out executed successfully"),
+            Err(e) => error!("Type-out failed: {}", e),
+        }
+    });
+}
+
+fn type_with_ydotool(text: &str, delay_ms: u64) -> Result<()> {
+    // Newlines are typed as Return by ydotool
+    run_with_stdin(
+        "ydotool",
+        &["type", "--key-delay", &delay_ms.to_string(), "--file", "-"],
+        text,
+    )
+}
+
+fn type_with_wtype(text: &str, delay_ms: u64) -> Result<()> {
+    // A lone "-" makes wtype read the text from stdin; newlines are typed as Return
+    run_with_stdin("wtype", &["-d", &delay_ms.to_string(), "-"], text)
+}
+
+fn run_with_stdin(program: &str, args: &[&str], input: &str) -> Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to execute {}: {}", program, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("{} exited with {}: {}", program, output.status, stderr.trim()));
+    }
+
+    Ok(())
+}
@@ -1,34 +1,75 @@
 use anyhow::Result;
 use gtk4::prelude::*;
 use gtk4::{
-    gdk, glib, Application, ApplicationWindow, Box, Entry, EventControllerKey, Label, ListBox,
-    ListBoxRow, Orientation, ScrolledWindow, SelectionMode,
+    gdk, gio, glib, Application, ApplicationWindow, Box, Entry, EventControllerKey, Image, Label,
+    ListBox, ListBoxRow, Notebook, Orientation, ScrolledWindow, SelectionMode,
 };
-use log::{debug, error, info, warn};
-use std::cell::RefCell;
+use log::error;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
 
-use crate::database::{ClipboardEntry, Database};
+use crate::actions::{ActionOutcome, ActionRegistry};
+use crate::classify::{classify, url_domain, ContentType};
+use crate::config::{Config, HistorySort, MergeConfig, MergeOrder, MergeSeparator};
+use crate::database::{ClipboardEntry, Database, Selection, Snippet};
+use crate::editor::{open_editor, SaveMode};
+use crate::hooks::Hooks;
+use crate::launch;
+use crate::paste::{copy_to_clipboard, spawn_auto_paste, spawn_type_out};
+use crate::plugins::Plugins;
+use crate::prompt::prompt;
+use crate::row_menu::show_row_menu;
+use crate::snippets::SnippetView;
+use crate::template::{self, TemplateContext};
+use crate::transform::TransformRegistry;
+
+const HISTORY_PAGE: u32 = 0;
+const SNIPPETS_PAGE: u32 = 1;
 
 pub struct ClipboardPopup {
     window: ApplicationWindow,
+    notebook: Notebook,
     list_box: ListBox,
+    snippet_view: SnippetView,
     search_entry: Entry,
+    stack_label: Label,
     entries: Rc<RefCell<Vec<ClipboardEntry>>>,
     filtered_entries: Rc<RefCell<Vec<ClipboardEntry>>>,
+    entry_tags: Rc<RefCell<HashMap<i64, Vec<String>>>>,
+    sort: Rc<Cell<HistorySort>>,
+    selection_order: Rc<RefCell<Vec<i64>>>,
     db: Arc<tokio::sync::Mutex<Database>>,
     runtime: Arc<Runtime>,
+    config: Config,
+    transforms: Rc<TransformRegistry>,
+    actions: Rc<ActionRegistry>,
+    hooks: Arc<Hooks>,
 }
 
 impl ClipboardPopup {
     pub fn new(app: &Application) -> Result<Self> {
         let runtime = Arc::new(Runtime::new()?);
+        let config = Config::load_or_default();
         let db = runtime.block_on(async {
             Database::new().await
         })?;
-        let db = Arc::new(tokio::sync::Mutex::new(db));
+        let db = Arc::new(tokio::sync::Mutex::new(db.with_blob_threshold(config.storage.blob_threshold)));
+        
+        // Plugins add their own transforms and quick actions
+        let history_db = db.clone();
+        let history_runtime = runtime.clone();
+        let plugins = Plugins::load(&config.plugins, Some(Arc::new(move |limit| {
+            history_runtime
+                .block_on(async { history_db.lock().await.get_recent_entries(limit).await })
+                .unwrap_or_default()
+        })));
+        let mut transforms = TransformRegistry::builtin();
+        plugins.register_transforms(&mut transforms);
+        let mut actions = ActionRegistry::builtin();
+        plugins.register_actions(&mut actions);
         
         // Create window
         let window = ApplicationWindow::builder()
@@ -59,6 +100,14 @@ impl ClipboardPopup {
         search_entry.add_css_class("search-entry");
         main_box.append(&search_entry);
         
+        // Remaining paste stack, hidden while the stack is empty
+        let stack_label = Label::new(None);
+        stack_label.add_css_class("stack-label");
+        stack_label.set_xalign(0.0);
+        stack_label.set_ellipsize(pango::EllipsizeMode::End);
+        stack_label.set_visible(false);
+        main_box.append(&stack_label);
+        
         // Create scrolled window
         let scrolled = ScrolledWindow::builder()
             .hscrollbar_policy(gtk4::PolicyType::Never)
@@ -68,26 +117,46 @@ impl ClipboardPopup {
         
         // Create list box
         let list_box = ListBox::new();
-        list_box.set_selection_mode(SelectionMode::Single);
+        list_box.set_selection_mode(SelectionMode::Multiple);
         list_box.add_css_class("clipboard-list");
         
         scrolled.set_child(Some(&list_box));
-        main_box.append(&scrolled);
+        
+        // History and snippets live in separate tabs
+        let snippet_view = SnippetView::new();
+        let notebook = Notebook::new();
+        notebook.add_css_class("popup-tabs");
+        notebook.set_focus_on_click(false);
+        notebook.append_page(&scrolled, Some(&Label::new(Some(history_tab_title(config.history.sort)))));
+        notebook.append_page(&snippet_view.scrolled, Some(&Label::new(Some("Snippets"))));
+        main_box.append(&notebook);
         
         window.set_child(Some(&main_box));
         
         let popup = Self {
             window,
+            notebook,
             list_box,
+            snippet_view,
             search_entry,
+            stack_label,
             entries: Rc::new(RefCell::new(Vec::new())),
             filtered_entries: Rc::new(RefCell::new(Vec::new())),
+            entry_tags: Rc::new(RefCell::new(HashMap::new())),
+            sort: Rc::new(Cell::new(config.history.sort)),
+            selection_order: Rc::new(RefCell::new(Vec::new())),
             db,
             runtime,
+            hooks: Arc::new(Hooks::new(config.hooks.clone())),
+            config,
+            transforms: Rc::new(transforms),
+            actions: Rc::new(actions),
         };
         
         popup.setup_keyboard_navigation();
+        popup.setup_snippet_keys();
         popup.setup_search_handler();
+        popup.setup_selection_tracking();
         popup.load_entries()?;
         
         Ok(popup)
@@ -117,90 +186,95 @@ impl ClipboardPopup {
         let db = self.db.clone();
         let runtime = self.runtime.clone();
         
-        let entries = runtime.block_on(async {
-            db.lock().await.get_recent_entries(60).await
+        let (entries, entry_tags) = runtime.block_on(async {
+            fetch_history(&*db.lock().await, self.sort.get()).await
         })?;
         
+        self.entry_tags.replace(entry_tags);
         self.entries.replace(entries.clone());
         self.filtered_entries.replace(entries.clone());
         
         self.update_list_display();
+        update_stack_label(&self.stack_label, &self.db, &self.runtime);
+        self.snippet_view.load(&self.db, &self.runtime);
         
         Ok(())
     }
     
     fn update_list_display(&self) {
-        // Clear existing rows
-        while let Some(child) = self.list_box.first_child() {
-            self.list_box.remove(&child);
-        }
-        
-        let entries = self.filtered_entries.borrow();
-        
-        // Add new rows
-        for (index, entry) in entries.iter().enumerate() {
-            let row = self.create_row(entry, index);
-            self.list_box.append(&row);
-        }
-    }
-    
-    fn create_row(&self, entry: &ClipboardEntry, index: usize) -> ListBoxRow {
-        let row = ListBoxRow::new();
-        row.add_css_class("clipboard-row");
-        
-        let hbox = Box::new(Orientation::Horizontal, 12);
-        hbox.set_margin_start(12);
-        hbox.set_margin_end(12);
-        hbox.set_margin_top(8);
-        hbox.set_margin_bottom(8);
-        
-        // Index label
-        let index_label = Label::new(Some(&format!("{}.", index + 1)));
-        index_label.add_css_class("index-label");
-        index_label.set_width_request(30);
-        hbox.append(&index_label);
-        
-        // Content label (truncated)
-        let content = if entry.content.len() > 80 {
-            format!("{}...", &entry.content[..80])
-        } else {
-            entry.content.clone()
-        };
-        
-        let content_label = Label::new(Some(&content));
-        content_label.add_css_class("content-label");
-        content_label.set_xalign(0.0);
-        content_label.set_hexpand(true);
-        content_label.set_ellipsize(pango::EllipsizeMode::End);
-        hbox.append(&content_label);
-        
-        // Default indicator
-        if entry.is_default {
-            let default_label = Label::new(Some("●"));
-            default_label.add_css_class("default-indicator");
-            hbox.append(&default_label);
-        }
-        
-        row.set_child(Some(&hbox));
-        row
+        show_entries(&self.list_box, &self.filtered_entries.borrow(), &self.entry_tags.borrow());
     }
     
     fn setup_keyboard_navigation(&self) {
         let key_controller = EventControllerKey::new();
         
         let window = self.window.clone();
+        let notebook = self.notebook.clone();
         let list_box = self.list_box.clone();
+        let snippet_view = self.snippet_view.clone();
         let entries = self.entries.clone();
         let filtered_entries = self.filtered_entries.clone();
+        let entry_tags = self.entry_tags.clone();
+        let sort = self.sort.clone();
         let search_entry = self.search_entry.clone();
+        let stack_label = self.stack_label.clone();
         let db = self.db.clone();
         let runtime = self.runtime.clone();
+        let selection_order = self.selection_order.clone();
+        let type_delay_ms = self.config.type_out.delay_ms;
+        let merge_config = self.config.merge.clone();
+        let transforms = self.transforms.clone();
+        let actions = self.actions.clone();
+        let hooks = self.hooks.clone();
         
         key_controller.connect_key_pressed(move |_, keyval, _, modifiers| {
+            // The snippets tab has its own key handling
+            if notebook.current_page() == Some(SNIPPETS_PAGE) {
+                return glib::Propagation::Proceed;
+            }
+            
             // Check if search is visible to determine behavior
             let search_is_visible = gtk4::prelude::WidgetExt::is_visible(&search_entry);
             
             match keyval {
+                gdk::Key::Tab | gdk::Key::ISO_Left_Tab if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    switch_tab(&notebook, &search_entry, SNIPPETS_PAGE, &snippet_view.list_box);
+                    glib::Propagation::Stop
+                }
+                gdk::Key::k if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    // Ctrl+K saves the selected entry as a snippet
+                    let selected = list_box
+                        .selected_row()
+                        .and_then(|row| filtered_entries.borrow().get(row.index() as usize).cloned());
+                    
+                    if let Some(entry) = selected {
+                        let name: String = entry.content.lines().next().unwrap_or_default().chars().take(40).collect();
+                        let fields = vec![
+                            ("Name".to_string(), name),
+                            ("Folder".to_string(), String::new()),
+                            ("Keyword".to_string(), String::new()),
+                        ];
+                        let db = db.clone();
+                        let runtime = runtime.clone();
+                        let snippet_view = snippet_view.clone();
+                        
+                        prompt(&window, "Save as snippet", &fields, move |values| {
+                            let (name, folder, keyword) = (&values[0], &values[1], &values[2]);
+                            if name.trim().is_empty() {
+                                return;
+                            }
+                            
+                            let result = runtime.block_on(async {
+                                db.lock().await.save_snippet(folder, name, Some(keyword), &entry.content).await
+                            });
+                            match result {
+                                Ok(_) => snippet_view.load(&db, &runtime),
+                                Err(e) => error!("Failed to save snippet: {}", e),
+                            }
+                        });
+                    }
+                    glib::Propagation::Stop
+                }
                 gdk::Key::Escape => {
                     if search_is_visible {
                         // Clear search first
@@ -209,17 +283,7 @@ impl ClipboardPopup {
                         // Restore all entries
                         filtered_entries.replace(entries.borrow().clone());
                         // Update display
-                        while let Some(child) = list_box.first_child() {
-                            list_box.remove(&child);
-                        }
-                        let all_entries = entries.borrow();
-                        for (index, entry) in all_entries.iter().enumerate() {
-                            let row = create_row_for_entry(entry, index);
-                            list_box.append(&row);
-                        }
-                        if let Some(first_row) = list_box.row_at_index(0) {
-                            list_box.select_row(Some(&first_row));
-                        }
+                        show_entries(&list_box, &entries.borrow(), &entry_tags.borrow());
                     } else {
                         window.close();
                     }
@@ -230,6 +294,18 @@ impl ClipboardPopup {
                     navigate_list(&list_box, 1);
                     glib::Propagation::Stop
                 }
+                gdk::Key::Return | gdk::Key::KP_Enter if modifiers.contains(gdk::ModifierType::SHIFT_MASK) => {
+                    // Shift+Enter types the entry out for apps that block paste
+                    if let Some(selected_row) = list_box.selected_row() {
+                        let index = selected_row.index() as usize;
+                        
+                        if let Some(entry) = filtered_entries.borrow().get(index) {
+                            spawn_type_out(entry.content.clone(), type_delay_ms);
+                            window.close();
+                        }
+                    }
+                    glib::Propagation::Stop
+                }
                 gdk::Key::Return | gdk::Key::KP_Enter => {
                     if let Some(selected_row) = list_box.selected_row() {
                         let index = selected_row.index() as usize;
@@ -256,9 +332,215 @@ impl ClipboardPopup {
                             // Spawn auto-paste as a detached process
                             spawn_auto_paste();
                             
-                            // Close window
+                            // Close window, then let the select hooks run in the background
+                            let app = window.application();
                             window.close();
+                            spawn_select_hooks(app, &runtime, &hooks, entry.clone());
+                        }
+                    }
+                    glib::Propagation::Stop
+                }
+                gdk::Key::s | gdk::Key::S if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    // Ctrl+S pushes the selected entries onto the paste stack, Ctrl+Shift+S clears it
+                    let clear = modifiers.contains(gdk::ModifierType::SHIFT_MASK);
+                    let selected = selected_entries(&list_box, &filtered_entries, &selection_order);
+                    
+                    runtime.block_on(async {
+                        let db = db.lock().await;
+                        let result = if clear {
+                            db.clear_stack().await
+                        } else {
+                            let mut result = Ok(());
+                            for entry in &selected {
+                                result = db.push_stack(entry.id).await;
+                                if result.is_err() {
+                                    break;
+                                }
+                            }
+                            result
+                        };
+                        if let Err(e) = result {
+                            error!("Failed to update paste stack: {}", e);
                         }
+                    });
+                    
+                    update_stack_label(&stack_label, &db, &runtime);
+                    if !clear && selected.len() == 1 {
+                        // Move on so repeated Ctrl+S stacks consecutive entries
+                        navigate_list(&list_box, 1);
+                    }
+                    glib::Propagation::Stop
+                }
+                gdk::Key::m if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    // Ctrl+M merges the selected entries into a new entry and pastes it
+                    let selected = selected_entries(&list_box, &filtered_entries, &selection_order);
+                    
+                    if selected.len() > 1 {
+                        let merged = merge_entries(selected, &merge_config);
+                        paste_new_entry(merged, &db, &runtime);
+                        window.close();
+                    }
+                    glib::Propagation::Stop
+                }
+                gdk::Key::e if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    // Ctrl+E edits the selected entry before pasting it
+                    let selected = list_box
+                        .selected_row()
+                        .and_then(|row| filtered_entries.borrow().get(row.index() as usize).cloned());
+                    
+                    if let Some(entry) = selected {
+                        let db = db.clone();
+                        let runtime = runtime.clone();
+                        let entries = entries.clone();
+                        let filtered_entries = filtered_entries.clone();
+                        let entry_tags = entry_tags.clone();
+                        let sort = sort.clone();
+                        let list_box = list_box.clone();
+                        
+                        open_editor(&window, &entry.content, move |content, mode| {
+                            let result = runtime.block_on(async {
+                                let db = db.lock().await;
+                                match mode {
+                                    SaveMode::Update => db.update_entry(entry.id, content).await,
+                                    SaveMode::AsNew => db.add_entry(content).await,
+                                }
+                            });
+                            
+                            match result {
+                                Ok(_) => reload_entries(&db, &runtime, sort.get(), &entries, &filtered_entries, &entry_tags, &list_box),
+                                Err(e) => error!("Failed to save edited entry: {}", e),
+                            }
+                        });
+                    }
+                    glib::Propagation::Stop
+                }
+                gdk::Key::r if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    // Ctrl+R switches between most recent and most used first
+                    let new_sort = match sort.get() {
+                        HistorySort::Recent => HistorySort::Frecency,
+                        HistorySort::Frecency => HistorySort::Recent,
+                    };
+                    sort.set(new_sort);
+                    
+                    if let Some(page) = notebook.nth_page(Some(HISTORY_PAGE)) {
+                        notebook.set_tab_label_text(&page, history_tab_title(new_sort));
+                    }
+                    search_entry.set_text("");
+                    search_entry.set_visible(false);
+                    reload_entries(&db, &runtime, new_sort, &entries, &filtered_entries, &entry_tags, &list_box);
+                    glib::Propagation::Stop
+                }
+                gdk::Key::g if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    // Ctrl+G edits the tags of the selected entry
+                    let selected = list_box
+                        .selected_row()
+                        .and_then(|row| filtered_entries.borrow().get(row.index() as usize).cloned());
+                    
+                    if let Some(entry) = selected {
+                        let current = entry_tags.borrow().get(&entry.id).cloned().unwrap_or_default();
+                        let fields = vec![("Tags".to_string(), current.join(" "))];
+                        let db = db.clone();
+                        let runtime = runtime.clone();
+                        let entries = entries.clone();
+                        let filtered_entries = filtered_entries.clone();
+                        let entry_tags = entry_tags.clone();
+                        let sort = sort.clone();
+                        let list_box = list_box.clone();
+                        
+                        prompt(&window, "Tags (separated by spaces)", &fields, move |values| {
+                            let wanted: Vec<&str> = values[0]
+                                .split(|c: char| c.is_whitespace() || c == ',')
+                                .map(|tag| tag.trim_start_matches('#'))
+                                .filter(|tag| !tag.is_empty())
+                                .collect();
+                            
+                            let result = runtime.block_on(async {
+                                let db = db.lock().await;
+                                for tag in current.iter().filter(|tag| !wanted.iter().any(|w| w.eq_ignore_ascii_case(tag))) {
+                                    db.untag_entry(entry.id, tag).await?;
+                                }
+                                for tag in &wanted {
+                                    db.tag_entry(entry.id, tag).await?;
+                                }
+                                anyhow::Ok(())
+                            });
+                            
+                            if let Err(e) = result {
+                                error!("Failed to update tags: {}", e);
+                            }
+                            reload_entries(&db, &runtime, sort.get(), &entries, &filtered_entries, &entry_tags, &list_box);
+                        });
+                    }
+                    glib::Propagation::Stop
+                }
+                gdk::Key::t if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    // Ctrl+T opens "paste as…" with the available transformations
+                    let selected = list_box.selected_row().and_then(|row| {
+                        let entry = filtered_entries.borrow().get(row.index() as usize).cloned();
+                        entry.map(|entry| (row, entry))
+                    });
+                    
+                    if let Some((row, entry)) = selected {
+                        let labels: Vec<String> = transforms.all().iter().map(|t| t.label.clone()).collect();
+                        let transforms = transforms.clone();
+                        let window = window.clone();
+                        let db = db.clone();
+                        let runtime = runtime.clone();
+                        
+                        show_row_menu(&row, "Paste as…", &labels, move |index| {
+                            let transform = &transforms.all()[index];
+                            match transform.apply(&entry.content) {
+                                Ok(result) => {
+                                    paste_new_entry(result, &db, &runtime);
+                                    window.close();
+                                    None
+                                }
+                                Err(e) => Some(format!("{} failed: {}", transform.label, e)),
+                            }
+                        });
+                    }
+                    glib::Propagation::Stop
+                }
+                gdk::Key::o if modifiers.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    // Ctrl+O opens the quick actions for the selected entry's content type
+                    let selected = list_box.selected_row().and_then(|row| {
+                        let entry = filtered_entries.borrow().get(row.index() as usize).cloned();
+                        entry.map(|entry| (row, entry))
+                    });
+                    
+                    if let Some((row, entry)) = selected {
+                        let (content_type, _) = entry_type(&entry);
+                        let available = actions.for_type(content_type);
+                        if available.is_empty() {
+                            return glib::Propagation::Stop;
+                        }
+                        
+                        let names: Vec<String> = available.iter().map(|a| a.name.clone()).collect();
+                        let labels: Vec<String> = available.iter().map(|a| a.label.clone()).collect();
+                        let actions = actions.clone();
+                        let window = window.clone();
+                        let db = db.clone();
+                        let runtime = runtime.clone();
+                        
+                        show_row_menu(&row, "Actions", &labels, move |index| {
+                            let action = actions.get(&names[index])?;
+                            let result = action.run(&entry.content).and_then(|outcome| match outcome {
+                                ActionOutcome::Open(uri) => launch::open_uri(&uri),
+                                ActionOutcome::Reveal(uri) => launch::reveal_uri(&uri),
+                                ActionOutcome::Paste(text) => {
+                                    paste_new_entry(text, &db, &runtime);
+                                    Ok(())
+                                }
+                            });
+                            
+                            match result {
+                                Ok(()) => {
+                                    window.close();
+                                    None
+                                }
+                                Err(e) => Some(format!("{} failed: {}", action.label, e)),
+                            }
+                        });
                     }
                     glib::Propagation::Stop
                 }
@@ -270,6 +552,14 @@ impl ClipboardPopup {
                     navigate_list(&list_box, -1);
                     glib::Propagation::Stop
                 }
+                gdk::Key::Down if modifiers.contains(gdk::ModifierType::SHIFT_MASK) => {
+                    extend_selection(&list_box, 1);
+                    glib::Propagation::Stop
+                }
+                gdk::Key::Up if modifiers.contains(gdk::ModifierType::SHIFT_MASK) => {
+                    extend_selection(&list_box, -1);
+                    glib::Propagation::Stop
+                }
                 gdk::Key::Down => {
                     navigate_list(&list_box, 1);
                     glib::Propagation::Stop
@@ -285,6 +575,7 @@ impl ClipboardPopup {
                             if num >= 1 && num <= 9 {
                                 let index = (num - 1) as i32;
                                 if let Some(row) = list_box.row_at_index(index) {
+                                    list_box.unselect_all();
                                     list_box.select_row(Some(&row));
                                     // Trigger the same action as Enter key
                                     if let Some(selected_row) = list_box.selected_row() {
@@ -312,8 +603,10 @@ impl ClipboardPopup {
                                             // Spawn auto-paste as a detached process
                                             spawn_auto_paste();
                                             
-                                            // Close window
+                                            // Close window, then let the select hooks run in the background
+                                            let app = window.application();
                                             window.close();
+                                            spawn_select_hooks(app, &runtime, &hooks, entry.clone());
                                         }
                                     }
                                 }
@@ -343,6 +636,95 @@ impl ClipboardPopup {
         self.window.add_controller(key_controller);
     }
     
+    fn setup_snippet_keys(&self) {
+        let key_controller = EventControllerKey::new();
+        
+        let window = self.window.clone();
+        let notebook = self.notebook.clone();
+        let list_box = self.list_box.clone();
+        let snippet_view = self.snippet_view.clone();
+        let search_entry = self.search_entry.clone();
+        let db = self.db.clone();
+        let runtime = self.runtime.clone();
+        
+        key_controller.connect_key_pressed(move |_, keyval, _, modifiers| {
+            if notebook.current_page() != Some(SNIPPETS_PAGE) {
+                return glib::Propagation::Proceed;
+            }
+            
+            let search_is_visible = gtk4::prelude::WidgetExt::is_visible(&search_entry);
+            let ctrl = modifiers.contains(gdk::ModifierType::CONTROL_MASK);
+            
+            match keyval {
+                gdk::Key::Escape => {
+                    if search_is_visible {
+                        search_entry.set_text("");
+                        search_entry.set_visible(false);
+                    } else {
+                        window.close();
+                    }
+                }
+                gdk::Key::Tab | gdk::Key::ISO_Left_Tab if ctrl => {
+                    switch_tab(&notebook, &search_entry, HISTORY_PAGE, &list_box);
+                }
+                gdk::Key::Return | gdk::Key::KP_Enter => {
+                    if let Some(snippet) = snippet_view.selected() {
+                        paste_snippet(&snippet, &window, &db, &runtime);
+                    }
+                }
+                gdk::Key::Delete => {
+                    if let Some(snippet) = snippet_view.selected() {
+                        let result = runtime.block_on(async { db.lock().await.delete_snippet(snippet.id).await });
+                        if let Err(e) = result {
+                            error!("Failed to delete snippet: {}", e);
+                        }
+                        snippet_view.load(&db, &runtime);
+                    }
+                }
+                gdk::Key::Down => navigate_list(&snippet_view.list_box, 1),
+                gdk::Key::Up => navigate_list(&snippet_view.list_box, -1),
+                gdk::Key::n if ctrl => navigate_list(&snippet_view.list_box, 1),
+                gdk::Key::p if ctrl => navigate_list(&snippet_view.list_box, -1),
+                gdk::Key::slash => {
+                    search_entry.set_visible(true);
+                    search_entry.grab_focus();
+                }
+                k if search_is_visible && k.to_unicode().is_some() => {
+                    search_entry.grab_focus();
+                    search_entry.set_position(-1);
+                }
+                // Don't let unhandled keys reach the history shortcuts
+                _ => {}
+            }
+            glib::Propagation::Stop
+        });
+        
+        self.window.add_controller(key_controller);
+    }
+    
+    /// Remember the order rows are selected in, for merging and stacking
+    fn setup_selection_tracking(&self) {
+        let filtered_entries = self.filtered_entries.clone();
+        let selection_order = self.selection_order.clone();
+        
+        self.list_box.connect_selected_rows_changed(move |list_box| {
+            let entries = filtered_entries.borrow();
+            let selected: Vec<i64> = list_box
+                .selected_rows()
+                .iter()
+                .filter_map(|row| entries.get(row.index() as usize).map(|entry| entry.id))
+                .collect();
+            
+            let mut order = selection_order.borrow_mut();
+            order.retain(|id| selected.contains(id));
+            for id in selected {
+                if !order.contains(&id) {
+                    order.push(id);
+                }
+            }
+        });
+    }
+    
     fn setup_search_handler(&self) {
         let entries = self.entries.clone();
         let filtered_entries = self.filtered_entries.clone();
@@ -354,76 +736,81 @@ impl ClipboardPopup {
         
         let popup_weak = Rc::downgrade(&Rc::new(RefCell::new(())));
         let list_box_clone = self.list_box.clone();
+        let notebook = self.notebook.clone();
+        let snippet_view = self.snippet_view.clone();
+        let entry_tags = self.entry_tags.clone();
+        let db = self.db.clone();
+        let runtime = self.runtime.clone();
+        let search_limit = self.config.storage.search_limit;
         
         search_entry_clone.connect_changed(move |entry| {
-            let query = entry.text().to_string().to_lowercase();
-            let all_entries = entries_clone.borrow();
-            
-            if query.is_empty() {
-                // Show all entries if search is empty
-                filtered_clone.replace(all_entries.clone());
-            } else {
-                // Filter entries based on search query (limit to top 15 matches)
-                let filtered: Vec<ClipboardEntry> = all_entries
-                    .iter()
-                    .filter(|entry| entry.content.to_lowercase().contains(&query))
-                    .take(15)
-                    .cloned()
-                    .collect();
-                filtered_clone.replace(filtered);
+            if notebook.current_page() == Some(SNIPPETS_PAGE) {
+                snippet_view.filter(&entry.text());
+                return;
             }
             
-            // Update the list display
-            while let Some(child) = list_box_clone.first_child() {
-                list_box_clone.remove(&child);
+            let query = entry.text().to_string().to_lowercase();
+            
+            // "#tag" words filter by tag, "type:url" words by content type,
+            // "in:primary" words by selection and the rest of the query by content
+            let mut tags = Vec::new();
+            let mut types = Vec::new();
+            let mut selections = Vec::new();
+            let mut words = Vec::new();
+            for word in query.split_whitespace() {
+                if word.len() > 1 && word.starts_with('#') {
+                    tags.push(word);
+                } else if let Some(name) = word.strip_prefix("type:") {
+                    types.push(name);
+                } else if let Some(name) = word.strip_prefix("in:") {
+                    selections.push(name);
+                } else {
+                    words.push(word);
+                }
             }
+            let text = words.join(" ");
             
-            let entries_to_show = filtered_clone.borrow();
-            for (index, entry) in entries_to_show.iter().enumerate() {
-                let row = ListBoxRow::new();
-                row.add_css_class("clipboard-row");
-                
-                let hbox = Box::new(Orientation::Horizontal, 12);
-                hbox.set_margin_start(12);
-                hbox.set_margin_end(12);
-                hbox.set_margin_top(8);
-                hbox.set_margin_bottom(8);
+            if tags.is_empty() {
+                let all_entries = entries_clone.borrow();
                 
-                // Index label
-                let index_label = Label::new(Some(&format!("{}.", index + 1)));
-                index_label.add_css_class("index-label");
-                index_label.set_width_request(30);
-                hbox.append(&index_label);
-                
-                // Content label (truncated)
-                let content = if entry.content.len() > 80 {
-                    format!("{}...", &entry.content[..80])
+                if text.is_empty() && types.is_empty() && selections.is_empty() {
+                    // Show all entries if search is empty
+                    filtered_clone.replace(all_entries.clone());
                 } else {
-                    entry.content.clone()
-                };
-                
-                let content_label = Label::new(Some(&content));
-                content_label.add_css_class("content-label");
-                content_label.set_xalign(0.0);
-                content_label.set_hexpand(true);
-                content_label.set_ellipsize(pango::EllipsizeMode::End);
-                hbox.append(&content_label);
-                
-                // Default indicator
-                if entry.is_default {
-                    let default_label = Label::new(Some("●"));
-                    default_label.add_css_class("default-indicator");
-                    hbox.append(&default_label);
+                    // Filter entries based on search query (limit to top 15 matches)
+                    let filtered: Vec<ClipboardEntry> = all_entries
+                        .iter()
+                        .filter(|entry| matches_search(entry, &text, search_limit) && matches_type(entry, &types))
+                        .filter(|entry| matches_selection(entry, &selections))
+                        .take(15)
+                        .cloned()
+                        .collect();
+                    filtered_clone.replace(filtered);
                 }
+            } else {
+                // Tagged entries come from the database, so older ones are found too
+                let tagged = runtime.block_on(async { db.lock().await.entries_with_tag(tags[0]).await });
+                let tagged = tagged.unwrap_or_else(|e| {
+                    error!("Failed to search tag: {}", e);
+                    Vec::new()
+                });
                 
-                row.set_child(Some(&hbox));
-                list_box_clone.append(&row);
+                let entry_tags = entry_tags.borrow();
+                let filtered: Vec<ClipboardEntry> = tagged
+                    .into_iter()
+                    .filter(|entry| {
+                        let entry_tags = entry_tags.get(&entry.id).map(Vec::as_slice).unwrap_or_default();
+                        tags[1..].iter().all(|tag| {
+                            entry_tags.iter().any(|t| t.to_lowercase() == tag[1..])
+                        })
+                    })
+                    .filter(|entry| matches_search(entry, &text, search_limit) && matches_type(entry, &types))
+                    .filter(|entry| matches_selection(entry, &selections))
+                    .collect();
+                filtered_clone.replace(filtered);
             }
             
-            // Select first row
-            if let Some(first_row) = list_box_clone.row_at_index(0) {
-                list_box_clone.select_row(Some(&first_row));
-            }
+            show_entries(&list_box_clone, &filtered_clone.borrow(), &entry_tags.borrow());
         });
         
         // Handle special keys in search entry
@@ -432,8 +819,20 @@ impl ClipboardPopup {
         let entries_clone = self.entries.clone();
         let filtered_clone = self.filtered_entries.clone();
         let list_box_for_nav = self.list_box.clone();
+        let notebook = self.notebook.clone();
+        let snippet_view = self.snippet_view.clone();
+        let snippet_list = self.snippet_view.list_box.clone();
+        let window = self.window.clone();
+        let db = self.db.clone();
+        let runtime = self.runtime.clone();
         
         search_key_controller.connect_key_pressed(move |_, keyval, _, _| {
+            let list_box_for_nav = if notebook.current_page() == Some(SNIPPETS_PAGE) {
+                &snippet_list
+            } else {
+                &list_box_for_nav
+            };
+            
             match keyval {
                 gdk::Key::Escape => {
                     // Clear search and hide entry
@@ -448,11 +847,18 @@ impl ClipboardPopup {
                     if let Some(first_row) = list_box_for_nav.row_at_index(0) {
                         first_row.grab_focus();
                         if keyval == gdk::Key::Down {
-                            navigate_list(&list_box_for_nav, 1);
+                            navigate_list(list_box_for_nav, 1);
                         }
                     }
                     glib::Propagation::Stop
                 }
+                gdk::Key::Return if notebook.current_page() == Some(SNIPPETS_PAGE) => {
+                    // Typing a snippet's keyword and pressing Enter pastes it
+                    if let Some(snippet) = snippet_view.selected() {
+                        paste_snippet(&snippet, &window, &db, &runtime);
+                    }
+                    glib::Propagation::Stop
+                }
                 gdk::Key::Return => {
                     // Allow Enter to select from search field
                     if let Some(selected) = list_box_for_nav.selected_row() {
@@ -465,10 +871,11 @@ impl ClipboardPopup {
         });
         
         self.search_entry.add_controller(search_key_controller);
+
     }
 }
 
-fn create_row_for_entry(entry: &ClipboardEntry, index: usize) -> ListBoxRow {
+fn create_row(entry: &ClipboardEntry, index: usize, tags: &[String]) -> ListBoxRow {
     let row = ListBoxRow::new();
     row.add_css_class("clipboard-row");
     
@@ -484,6 +891,39 @@ fn create_row_for_entry(entry: &ClipboardEntry, index: usize) -> ListBoxRow {
     index_label.set_width_request(30);
     hbox.append(&index_label);
     
+    // Icon of the application the entry was copied from
+    if let Some(app) = &entry.source_app {
+        let icon = source_icon(app);
+        icon.add_css_class("source-icon");
+        icon.set_pixel_size(16);
+        hbox.append(&icon);
+    }
+    
+    // Marker for text that was selected rather than copied
+    if entry.selection == Selection::Primary {
+        let icon = Image::from_icon_name("input-mouse-symbolic");
+        icon.add_css_class("selection-icon");
+        hbox.append(&icon);
+    }
+    
+    let mut source: Vec<&str> = [&entry.source_app, &entry.window_title, &entry.source_url]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if entry.selection == Selection::Primary {
+        source.push("Primary selection");
+    }
+    if !source.is_empty() {
+        row.set_tooltip_text(Some(&source.join("\n")));
+    }
+    
+    // Type icon, or a swatch for colors
+    let (content_type, language) = entry_type(entry);
+    if let Some(indicator) = type_indicator(content_type, &entry.content) {
+        hbox.append(&indicator);
+    }
+    
     // Content label (truncated)
     let content = if entry.content.len() > 80 {
         format!("{}...", &entry.content[..80])
@@ -498,6 +938,54 @@ fn create_row_for_entry(entry: &ClipboardEntry, index: usize) -> ListBoxRow {
     content_label.set_ellipsize(pango::EllipsizeMode::End);
     hbox.append(&content_label);
     
+    // Domain of URLs and language of code
+    let preview = match content_type {
+        ContentType::Url => url_domain(entry.content.trim()).map(String::from),
+        ContentType::Code => language,
+        _ => None,
+    };
+    if let Some(preview) = preview {
+        let preview_label = Label::new(Some(&preview));
+        preview_label.add_css_class("type-preview");
+        hbox.append(&preview_label);
+    }
+    
+    // Tag chips
+    for tag in tags {
+        let tag_label = Label::new(Some(&format!("#{}", tag)));
+        tag_label.add_css_class("tag-chip");
+        hbox.append(&tag_label);
+    }
+    
+    // Machine a shared entry came from
+    if let Some(origin_host) = &entry.origin_host {
+        let origin_label = Label::new(Some(&format!("from {}", origin_host)));
+        origin_label.add_css_class("origin-host");
+        origin_label.set_tooltip_text(Some("Shared from another machine"));
+        hbox.append(&origin_label);
+    }
+    
+    // Countdown to the entry removing itself
+    if let Some(expires_at) = entry.expires_at {
+        let expiry_label = Label::new(Some(&expiry_text(expires_at)));
+        expiry_label.add_css_class("expiry-countdown");
+        expiry_label.set_tooltip_text(Some("Removed from the history when the time is up"));
+        hbox.append(&expiry_label);
+
+        let weak_label = expiry_label.downgrade();
+        glib::timeout_add_seconds_local(1, move || {
+            let Some(label) = weak_label.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            label.set_text(&expiry_text(expires_at));
+            if expires_at <= chrono::Utc::now() {
+                glib::ControlFlow::Break
+            } else {
+                glib::ControlFlow::Continue
+            }
+        });
+    }
+    
     // Default indicator
     if entry.is_default {
         let default_label = Label::new(Some("●"));
@@ -509,131 +997,337 @@ fn create_row_for_entry(entry: &ClipboardEntry, index: usize) -> ListBoxRow {
     row
 }
 
-fn navigate_list(list_box: &ListBox, direction: i32) {
-    let current_index = list_box
-        .selected_row()
-        .map(|row| row.index())
-        .unwrap_or(-1);
-    
-    let new_index = current_index + direction;
+/// Time left until `expires_at`, as shown on the row
+fn expiry_text(expires_at: chrono::DateTime<chrono::Utc>) -> String {
+    let seconds = (expires_at - chrono::Utc::now()).num_seconds();
+    if seconds <= 0 {
+        return "expired".to_string();
+    }
+    match seconds {
+        0..=3599 => format!("⏱ {}:{:02}", seconds / 60, seconds % 60),
+        _ => format!("⏱ {}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+/// Icon of a desktop application, from its .desktop file or the icon theme
+fn source_icon(app: &str) -> Image {
+    for id in [app.to_string(), app.to_lowercase()] {
+        let icon = gio::DesktopAppInfo::new(&format!("{}.desktop", id)).and_then(|info| info.icon());
+        if let Some(icon) = icon {
+            return Image::from_gicon(&icon);
+        }
+    }
     
-    if let Some(new_row) = list_box.row_at_index(new_index) {
-        list_box.select_row(Some(&new_row));
-        new_row.grab_focus();
+    Image::from_icon_name(&app.to_lowercase())
+}
+
+/// The stored classification of an entry, or a fresh one for entries the
+/// daemon hasn't classified (older or edited ones)
+fn entry_type(entry: &ClipboardEntry) -> (ContentType, Option<String>) {
+    match entry.content_type.as_deref().and_then(ContentType::from_name) {
+        Some(content_type) => (content_type, entry.language.clone()),
+        None => {
+            let classification = classify(&entry.content);
+            (classification.content_type, classification.language.map(String::from))
+        }
     }
 }
 
-fn copy_to_clipboard(content: &str) -> Result<()> {
-    use arboard::Clipboard;
+fn type_indicator(content_type: ContentType, content: &str) -> Option<gtk4::Widget> {
+    let icon_name = match content_type {
+        ContentType::Color => {
+            let color = gdk::RGBA::parse(content.trim()).ok()?;
+            let swatch = gtk4::DrawingArea::new();
+            swatch.set_content_width(16);
+            swatch.set_content_height(16);
+            swatch.set_valign(gtk4::Align::Center);
+            swatch.add_css_class("color-swatch");
+            swatch.set_draw_func(move |_, cr, width, height| {
+                cr.set_source_rgba(
+                    color.red() as f64,
+                    color.green() as f64,
+                    color.blue() as f64,
+                    color.alpha() as f64,
+                );
+                cr.rectangle(0.0, 0.0, width as f64, height as f64);
+                let _ = cr.fill();
+            });
+            return Some(swatch.upcast());
+        }
+        ContentType::Url => "web-browser-symbolic",
+        ContentType::Email => "mail-unread-symbolic",
+        ContentType::Path => "folder-symbolic",
+        ContentType::Number => "accessories-calculator-symbolic",
+        ContentType::Json | ContentType::Code => "text-x-script-symbolic",
+        ContentType::Command => "utilities-terminal-symbolic",
+        ContentType::Text => return None,
+    };
+    
+    let icon = Image::from_icon_name(icon_name);
+    icon.add_css_class("type-icon");
+    Some(icon.upcast())
+}
+
+/// Whether an entry has one of the `type:` filters, if there are any
+fn matches_type(entry: &ClipboardEntry, types: &[&str]) -> bool {
+    types.is_empty() || types.contains(&entry_type(entry).0.name())
+}
+
+/// Whether an entry is from one of the `in:` selections, if there are any
+fn matches_selection(entry: &ClipboardEntry, selections: &[&str]) -> bool {
+    selections.is_empty() || selections.contains(&entry.selection.name())
+}
+
+/// Whether the content or the source of an entry contains `text` (already
+/// lowercase). Content over `search_limit` bytes is not searched.
+fn matches_search(entry: &ClipboardEntry, text: &str, search_limit: usize) -> bool {
+    (entry.content.len() <= search_limit && entry.content.to_lowercase().contains(text))
+        || [&entry.source_app, &entry.window_title, &entry.source_url]
+            .into_iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(text))
+}
+
+/// Replace the rows of `list_box` with `entries` and select the first one
+fn show_entries(list_box: &ListBox, entries: &[ClipboardEntry], entry_tags: &HashMap<i64, Vec<String>>) {
+    while let Some(child) = list_box.first_child() {
+        list_box.remove(&child);
+    }
     
-    let mut clipboard = Clipboard::new()
-        .map_err(|e| anyhow::anyhow!("Failed to access clipboard: {}", e))?;
+    for (index, entry) in entries.iter().enumerate() {
+        let tags = entry_tags.get(&entry.id).map(Vec::as_slice).unwrap_or_default();
+        list_box.append(&create_row(entry, index, tags));
+    }
     
-    clipboard.set_text(content)
-        .map_err(|e| anyhow::anyhow!("Failed to set clipboard content: {}", e))?;
+    if let Some(first_row) = list_box.row_at_index(0) {
+        list_box.select_row(Some(&first_row));
+    }
+}
+
+fn switch_tab(notebook: &Notebook, search_entry: &Entry, page: u32, list_box: &ListBox) {
+    search_entry.set_text("");
+    search_entry.set_visible(false);
+    notebook.set_current_page(Some(page));
     
-    info!("Copied to clipboard using arboard");
-    Ok(())
+    if let Some(row) = list_box.selected_row().or_else(|| list_box.row_at_index(0)) {
+        list_box.select_row(Some(&row));
+        row.grab_focus();
+    }
 }
 
-fn spawn_auto_paste() {
-    use std::process::Command;
-    use std::thread;
-    use std::time::Duration;
-    
-    // Spawn a thread to handle auto-paste after a short delay
-    thread::spawn(|| {
-        // Small delay to ensure window closes and focus returns
-        thread::sleep(Duration::from_millis(200));
-        
-        // Always use Ctrl+Shift+V for paste
-        info!("Executing auto-paste with Ctrl+Shift+V");
-        
-        // Execute ydotool
-        match Command::new("ydotool")
-            .args(&["key", "ctrl+shift+v"])
-            .output()
-        {
-            Ok(output) => {
-                if output.status.success() {
-                    info!("Auto-paste executed successfully");
-                } else {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    warn!("ydotool failed: {}", stderr);
-                }
-            }
-            Err(e) => {
-                warn!("Failed to execute ydotool: {}. Trying wtype fallback...", e);
-                
-                // Try wtype as fallback
-                if let Err(e) = Command::new("wtype")
-                    .args(&["-M", "ctrl", "-M", "shift", "-P", "v", "-m", "shift", "-m", "ctrl"])
-                    .output() 
-                {
-                    error!("Both ydotool and wtype failed: {}", e);
-                }
+/// Expand the snippet's placeholders, asking for any inputs first, then paste
+/// the result and close the popup
+fn paste_snippet(
+    snippet: &Snippet,
+    window: &ApplicationWindow,
+    db: &Arc<tokio::sync::Mutex<Database>>,
+    runtime: &Arc<Runtime>,
+) {
+    let clipboard = match runtime.block_on(async { db.lock().await.get_default_entry().await }) {
+        Ok(entry) => entry.map(|entry| entry.content).unwrap_or_default(),
+        Err(e) => {
+            error!("Failed to load the current entry: {}", e);
+            String::new()
+        }
+    };
+    
+    let content = snippet.content.clone();
+    let labels = template::input_labels(&content);
+    let paste = {
+        let window = window.clone();
+        move |inputs: HashMap<String, String>| {
+            let expanded = template::expand(&content, &TemplateContext { clipboard: &clipboard, inputs: &inputs });
+            if let Err(e) = copy_to_clipboard(&expanded) {
+                error!("Failed to copy to clipboard: {}", e);
             }
+            
+            window.close();
+            spawn_auto_paste();
         }
+    };
+    
+    if labels.is_empty() {
+        paste(HashMap::new());
+        return;
+    }
+    
+    let fields: Vec<(String, String)> = labels.iter().map(|label| (label.clone(), String::new())).collect();
+    prompt(window, &snippet.name, &fields, move |values| {
+        paste(labels.iter().cloned().zip(values).collect());
     });
 }
 
-fn simulate_paste() -> Result<()> {
-    use std::process::Command;
-    use std::thread;
-    use std::time::Duration;
-    
-    // Small delay to ensure window closes first
-    thread::sleep(Duration::from_millis(200));
-    
-    // Try ydotool first with regular Ctrl+V
-    info!("Attempting to auto-paste with ydotool...");
-    let ydotool_result = Command::new("ydotool")
-        .args(&["key", "ctrl+v"])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .output();
-    
-    match ydotool_result {
-        Ok(output) => {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            info!("ydotool exit status: {}, stdout: {}, stderr: {}", output.status.success(), stdout, stderr);
-            
-            if output.status.success() {
-                info!("Pasted using ydotool");
-                return Ok(());
-            } else {
-                warn!("ydotool failed with stderr: {}", stderr);
-            }
+/// The history in the given order, with the tags of each entry
+async fn fetch_history(
+    db: &Database,
+    sort: HistorySort,
+) -> Result<(Vec<ClipboardEntry>, HashMap<i64, Vec<String>>)> {
+    let entries = match sort {
+        HistorySort::Recent => db.get_recent_entries(60).await?,
+        HistorySort::Frecency => db.get_frecent_entries(60).await?,
+    };
+    Ok((entries, db.get_entry_tags().await?))
+}
+
+fn history_tab_title(sort: HistorySort) -> &'static str {
+    match sort {
+        HistorySort::Recent => "History",
+        HistorySort::Frecency => "History (most used)",
+    }
+}
+
+/// Run the select hooks without blocking the UI. The application is held
+/// until they are done, so they aren't killed when the last window closes.
+fn spawn_select_hooks(app: Option<Application>, runtime: &Runtime, hooks: &Arc<Hooks>, entry: ClipboardEntry) {
+    let hooks = hooks.clone();
+    let task = runtime.spawn(async move { hooks.on_select(&entry).await });
+    let hold = app.map(|app| app.hold());
+    glib::MainContext::default().spawn_local(async move {
+        if let Err(e) = task.await {
+            error!("Select hooks failed: {}", e);
         }
+        drop(hold);
+    });
+}
+
+/// Re-read the history after it was changed from the popup
+fn reload_entries(
+    db: &Arc<tokio::sync::Mutex<Database>>,
+    runtime: &Arc<Runtime>,
+    sort: HistorySort,
+    entries: &Rc<RefCell<Vec<ClipboardEntry>>>,
+    filtered_entries: &Rc<RefCell<Vec<ClipboardEntry>>>,
+    entry_tags: &Rc<RefCell<HashMap<i64, Vec<String>>>>,
+    list_box: &ListBox,
+) {
+    let loaded = runtime.block_on(async { fetch_history(&*db.lock().await, sort).await });
+    let (loaded, tags) = match loaded {
+        Ok(loaded) => loaded,
         Err(e) => {
-            warn!("ydotool not found or failed to run: {}", e);
+            error!("Failed to reload entries: {}", e);
+            return;
         }
-    }
+    };
     
-    // Fallback to wtype
-    let wtype_result = Command::new("wtype")
-        .args(&["-M", "ctrl", "-P", "v", "-m", "ctrl"])
-        .stderr(std::process::Stdio::piped())
-        .output();
+    entry_tags.replace(tags);
+    entries.replace(loaded.clone());
+    filtered_entries.replace(loaded);
     
-    match wtype_result {
-        Ok(output) => {
-            if output.status.success() {
-                info!("Pasted using wtype");
-                return Ok(());
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                debug!("wtype failed: {}", stderr);
-            }
+    show_entries(list_box, &filtered_entries.borrow(), &entry_tags.borrow());
+    if let Some(first_row) = list_box.row_at_index(0) {
+        first_row.grab_focus();
+    }
+}
+
+/// Store `content` as a new history entry, then copy and auto-paste it
+fn paste_new_entry(
+    content: String,
+    db: &Arc<tokio::sync::Mutex<Database>>,
+    runtime: &Arc<Runtime>,
+) {
+    runtime.block_on(async {
+        if let Err(e) = db.lock().await.add_entry(content.clone()).await {
+            error!("Failed to save new entry: {}", e);
         }
+    });
+    
+    if let Err(e) = copy_to_clipboard(&content) {
+        error!("Failed to copy to clipboard: {}", e);
+    }
+    
+    spawn_auto_paste();
+}
+
+fn update_stack_label(
+    stack_label: &Label,
+    db: &Arc<tokio::sync::Mutex<Database>>,
+    runtime: &Arc<Runtime>,
+) {
+    let stack = match runtime.block_on(async { db.lock().await.get_stack_entries().await }) {
+        Ok(stack) => stack,
         Err(e) => {
-            debug!("wtype not found or failed to run: {}", e);
+            error!("Failed to load paste stack: {}", e);
+            Vec::new()
         }
+    };
+    
+    let items: Vec<String> = stack
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let line = entry.content.lines().next().unwrap_or_default();
+            format!("{}. {}", index + 1, line.chars().take(30).collect::<String>())
+        })
+        .collect();
+    
+    stack_label.set_text(&format!("Paste stack ({}): {}", stack.len(), items.join("  ·  ")));
+    stack_label.set_visible(!stack.is_empty());
+}
+
+/// Selected entries, in the order their rows were selected
+fn selected_entries(
+    list_box: &ListBox,
+    filtered_entries: &Rc<RefCell<Vec<ClipboardEntry>>>,
+    selection_order: &Rc<RefCell<Vec<i64>>>,
+) -> Vec<ClipboardEntry> {
+    let entries = filtered_entries.borrow();
+    let order = selection_order.borrow();
+    
+    let mut selected: Vec<ClipboardEntry> = list_box
+        .selected_rows()
+        .iter()
+        .filter_map(|row| entries.get(row.index() as usize).cloned())
+        .collect();
+    selected.sort_by_key(|entry| order.iter().position(|id| *id == entry.id).unwrap_or(usize::MAX));
+    selected
+}
+
+fn merge_entries(mut entries: Vec<ClipboardEntry>, merge: &MergeConfig) -> String {
+    if merge.order == MergeOrder::Chronological {
+        entries.sort_by_key(|entry| entry.timestamp);
+    }
+    
+    let separator = match &merge.separator {
+        MergeSeparator::Newline => "\n",
+        MergeSeparator::Comma => ", ",
+        MergeSeparator::Space => " ",
+        MergeSeparator::Custom(separator) => separator.as_str(),
+    };
+    
+    entries
+        .iter()
+        .map(|entry| entry.content.as_str())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn navigate_list(list_box: &ListBox, direction: i32) {
+    let new_index = current_row_index(list_box) + direction;
+    
+    if let Some(new_row) = list_box.row_at_index(new_index) {
+        // Plain navigation collapses a multi-selection to the new row
+        list_box.unselect_all();
+        list_box.select_row(Some(&new_row));
+        new_row.grab_focus();
     }
+}
+
+/// Add the next row in `direction` to the selection (Shift+Up/Down)
+fn extend_selection(list_box: &ListBox, direction: i32) {
+    let new_index = current_row_index(list_box) + direction;
     
-    // If both fail, just log a warning but don't error out
-    // The content is already in the clipboard, user can paste manually
-    warn!("Auto-paste failed. Content is in clipboard - press Ctrl+V to paste manually.");
-    Ok(())
-}
\ No newline at end of file
+    if let Some(new_row) = list_box.row_at_index(new_index) {
+        list_box.select_row(Some(&new_row));
+        new_row.grab_focus();
+    }
+}
+
+/// Index of the focused row, falling back to the first selected one
+fn current_row_index(list_box: &ListBox) -> i32 {
+    list_box
+        .focus_child()
+        .and_then(|child| child.downcast::<ListBoxRow>().ok())
+        .or_else(|| list_box.selected_row())
+        .map(|row| row.index())
+        .unwrap_or(-1)
+}
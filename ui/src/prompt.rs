@@ -0,0 +1,85 @@
+use gtk4::prelude::*;
+use gtk4::{
+    gdk, glib, ApplicationWindow, Box, Entry, EventControllerKey, Grid, Label, Orientation, Window,
+};
+
+/// Ask for one or more values in a small form on top of the popup.
+///
+/// `fields` are `(label, initial value)` pairs. Enter moves to the next field
+/// and submits on the last one; Escape cancels without calling `on_submit`.
+pub fn prompt<F>(parent: &ApplicationWindow, title: &str, fields: &[(String, String)], on_submit: F)
+where
+    F: Fn(Vec<String>) + 'static,
+{
+    let window = Window::builder()
+        .transient_for(parent)
+        .title(title)
+        .default_width(420)
+        .decorated(false)
+        .modal(true)
+        .build();
+    window.add_css_class("clipboard-popup");
+
+    let vbox = Box::new(Orientation::Vertical, 8);
+    vbox.add_css_class("popup-container");
+
+    let title_label = Label::new(Some(title));
+    title_label.add_css_class("row-menu-title");
+    title_label.set_xalign(0.0);
+    vbox.append(&title_label);
+
+    let grid = Grid::new();
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(12);
+
+    let mut entries = Vec::new();
+    for (row, (label, initial)) in fields.iter().enumerate() {
+        let label = Label::new(Some(label));
+        label.add_css_class("prompt-label");
+        label.set_xalign(1.0);
+        grid.attach(&label, 0, row as i32, 1, 1);
+
+        let entry = Entry::new();
+        entry.add_css_class("search-entry");
+        entry.set_text(initial);
+        entry.set_hexpand(true);
+        grid.attach(&entry, 1, row as i32, 1, 1);
+        entries.push(entry);
+    }
+    vbox.append(&grid);
+    window.set_child(Some(&vbox));
+
+    let on_submit = std::rc::Rc::new(on_submit);
+    for (index, entry) in entries.iter().enumerate() {
+        let window = window.clone();
+        let entries = entries.clone();
+        let on_submit = on_submit.clone();
+
+        entry.connect_activate(move |_| match entries.get(index + 1) {
+            Some(next) => {
+                next.grab_focus();
+            }
+            None => {
+                let values = entries.iter().map(|entry| entry.text().to_string()).collect();
+                window.close();
+                on_submit(values);
+            }
+        });
+    }
+
+    let key_controller = EventControllerKey::new();
+    let prompt_window = window.clone();
+    key_controller.connect_key_pressed(move |_, keyval, _, _| match keyval {
+        gdk::Key::Escape => {
+            prompt_window.close();
+            glib::Propagation::Stop
+        }
+        _ => glib::Propagation::Proceed,
+    });
+    window.add_controller(key_controller);
+
+    window.present();
+    if let Some(first) = entries.first() {
+        first.grab_focus();
+    }
+}
@@ -0,0 +1,72 @@
+use gtk4::prelude::*;
+use gtk4::{
+    gdk, glib, Box, EventControllerKey, Label, ListBox, ListBoxRow, Orientation, Popover,
+    PositionType, SelectionMode,
+};
+
+/// Show a keyboard-driven menu below `row`.
+///
+/// `on_select` gets the index of the chosen item. It returns `None` once the
+/// choice is handled, or an error message that is shown in the menu.
+pub fn show_row_menu<F>(row: &ListBoxRow, title: &str, items: &[String], on_select: F)
+where
+    F: Fn(usize) -> Option<String> + 'static,
+{
+    let popover = Popover::new();
+    popover.add_css_class("row-menu");
+    popover.set_position(PositionType::Bottom);
+    popover.set_parent(row);
+
+    let vbox = Box::new(Orientation::Vertical, 4);
+
+    let title_label = Label::new(Some(title));
+    title_label.add_css_class("row-menu-title");
+    title_label.set_xalign(0.0);
+    vbox.append(&title_label);
+
+    let list = ListBox::new();
+    list.set_selection_mode(SelectionMode::Single);
+    list.add_css_class("row-menu-list");
+    for item in items {
+        let label = Label::new(Some(item));
+        label.set_xalign(0.0);
+        list.append(&label);
+    }
+    vbox.append(&list);
+
+    let error_label = Label::new(None);
+    error_label.add_css_class("row-menu-error");
+    error_label.set_wrap(true);
+    error_label.set_xalign(0.0);
+    error_label.set_visible(false);
+    vbox.append(&error_label);
+
+    popover.set_child(Some(&vbox));
+
+    list.connect_row_activated(move |_, item_row| {
+        if let Some(message) = on_select(item_row.index() as usize) {
+            error_label.set_text(&message);
+            error_label.set_visible(true);
+        }
+    });
+
+    // Keep keys that the menu doesn't handle away from the popup's shortcuts
+    let key_controller = EventControllerKey::new();
+    key_controller.connect_key_pressed(|_, keyval, _, _| match keyval {
+        gdk::Key::Escape => glib::Propagation::Proceed,
+        _ => glib::Propagation::Stop,
+    });
+    popover.add_controller(key_controller);
+
+    popover.connect_closed(|popover| {
+        let popover = popover.clone();
+        glib::idle_add_local_once(move || popover.unparent());
+    });
+
+    popover.popup();
+
+    if let Some(first) = list.row_at_index(0) {
+        list.select_row(Some(&first));
+        first.grab_focus();
+    }
+}
@@ -0,0 +1,145 @@
+use gtk4::prelude::*;
+use gtk4::{Box, Label, ListBox, ListBoxRow, Orientation, ScrolledWindow, SelectionMode};
+use log::error;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+use crate::database::{Database, Snippet};
+
+/// The "Snippets" tab of the popup
+#[derive(Clone)]
+pub struct SnippetView {
+    pub scrolled: ScrolledWindow,
+    pub list_box: ListBox,
+    snippets: Rc<RefCell<Vec<Snippet>>>,
+    filtered: Rc<RefCell<Vec<Snippet>>>,
+}
+
+impl SnippetView {
+    pub fn new() -> Self {
+        let list_box = ListBox::new();
+        list_box.set_selection_mode(SelectionMode::Single);
+        list_box.add_css_class("clipboard-list");
+
+        let scrolled = ScrolledWindow::builder()
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .vscrollbar_policy(gtk4::PolicyType::Automatic)
+            .vexpand(true)
+            .child(&list_box)
+            .build();
+
+        Self {
+            scrolled,
+            list_box,
+            snippets: Rc::new(RefCell::new(Vec::new())),
+            filtered: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    pub fn load(&self, db: &Arc<tokio::sync::Mutex<Database>>, runtime: &Arc<Runtime>) {
+        match runtime.block_on(async { db.lock().await.get_snippets().await }) {
+            Ok(snippets) => {
+                self.filtered.replace(snippets.clone());
+                self.snippets.replace(snippets);
+            }
+            Err(e) => error!("Failed to load snippets: {}", e),
+        }
+        self.update_list_display();
+    }
+
+    /// Show snippets matching `query`. A snippet whose keyword trigger is
+    /// exactly the query comes first, so typing it and pressing Enter pastes it.
+    pub fn filter(&self, query: &str) {
+        let query = query.trim().to_lowercase();
+        let all = self.snippets.borrow();
+
+        let filtered = if query.is_empty() {
+            all.clone()
+        } else {
+            let mut matches: Vec<Snippet> = all
+                .iter()
+                .filter(|s| {
+                    s.keyword.as_deref().is_some_and(|k| k.to_lowercase().contains(&query))
+                        || s.name.to_lowercase().contains(&query)
+                        || s.folder.to_lowercase().contains(&query)
+                        || s.content.to_lowercase().contains(&query)
+                })
+                .cloned()
+                .collect();
+            matches.sort_by_key(|s| s.keyword.as_deref().map(str::to_lowercase) != Some(query.clone()));
+            matches
+        };
+
+        self.filtered.replace(filtered);
+        self.update_list_display();
+    }
+
+    pub fn selected(&self) -> Option<Snippet> {
+        let row = self.list_box.selected_row()?;
+        self.filtered.borrow().get(row.index() as usize).cloned()
+    }
+
+    fn update_list_display(&self) {
+        while let Some(child) = self.list_box.first_child() {
+            self.list_box.remove(&child);
+        }
+
+        for (index, snippet) in self.filtered.borrow().iter().enumerate() {
+            self.list_box.append(&create_snippet_row(snippet, index));
+        }
+
+        if let Some(first_row) = self.list_box.row_at_index(0) {
+            self.list_box.select_row(Some(&first_row));
+        }
+    }
+}
+
+fn create_snippet_row(snippet: &Snippet, index: usize) -> ListBoxRow {
+    let row = ListBoxRow::new();
+    row.add_css_class("clipboard-row");
+
+    let hbox = Box::new(Orientation::Horizontal, 12);
+    hbox.set_margin_start(12);
+    hbox.set_margin_end(12);
+    hbox.set_margin_top(8);
+    hbox.set_margin_bottom(8);
+
+    let index_label = Label::new(Some(&format!("{}.", index + 1)));
+    index_label.add_css_class("index-label");
+    index_label.set_width_request(30);
+    hbox.append(&index_label);
+
+    let vbox = Box::new(Orientation::Vertical, 2);
+    vbox.set_hexpand(true);
+
+    let title = if snippet.folder.is_empty() {
+        snippet.name.clone()
+    } else {
+        format!("{} / {}", snippet.folder, snippet.name)
+    };
+    let name_label = Label::new(Some(&title));
+    name_label.add_css_class("snippet-name");
+    name_label.set_xalign(0.0);
+    name_label.set_ellipsize(pango::EllipsizeMode::End);
+    vbox.append(&name_label);
+
+    let preview = snippet.content.lines().next().unwrap_or_default();
+    let content_label = Label::new(Some(preview));
+    content_label.add_css_class("content-label");
+    content_label.set_xalign(0.0);
+    content_label.set_ellipsize(pango::EllipsizeMode::End);
+    vbox.append(&content_label);
+
+    hbox.append(&vbox);
+
+    if let Some(keyword) = &snippet.keyword {
+        let keyword_label = Label::new(Some(keyword));
+        keyword_label.add_css_class("snippet-keyword");
+        hbox.append(&keyword_label);
+    }
+
+    row.set_child(Some(&hbox));
+    row
+}